@@ -1,89 +1,703 @@
 use std::{
-    fs::File,
-    io::{prelude::*, BufReader},
+    env, fmt,
+    fs::{self, File},
+    io::{self, prelude::*, BufReader},
     path::Path,
+    str::FromStr,
 };
 
-// I could use a struct and/or enum here, but honestly I don't care
+#[derive(Debug)]
+struct ParseErr(String);
+
 fn read_file(filename: impl AsRef<Path>) -> Vec<String> {
     let file = File::open(filename).expect("File not found");
     let buf = BufReader::new(file);
     buf.lines().map(|l| l.unwrap()).collect()
 }
 
-fn get_illegal_score(c: char) -> u64 {
-    match c {
-        ')' => 3,
-        ']' => 57,
-        '}' => 1197,
-        '>' => 25137,
-        _ => panic!("unexpected character '{}'", c),
+/*
+one definition per line:
+    pair <open> <close> <illegal score> <autocomplete points>
+    ignore <chars>
+*/
+const STANDARD: &str = "pair ( ) 3 1
+pair [ ] 57 2
+pair { } 1197 3
+pair < > 25137 4";
+
+#[derive(Debug, Clone, Copy)]
+struct Pair {
+    open: char,
+    close: char,
+    illegal_score: u64,
+    ac_points: u64,
+}
+
+#[derive(Debug)]
+struct BracketLanguage {
+    pairs: Vec<Pair>,
+    ignored: Vec<char>,
+}
+
+impl FromStr for BracketLanguage {
+    type Err = ParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut language = BracketLanguage {
+            pairs: Vec::new(),
+            ignored: Vec::new(),
+        };
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts[..] {
+                ["pair", open, close, illegal_score, ac_points] => {
+                    let char_of = |s: &str| {
+                        let mut chars = s.chars();
+                        match (chars.next(), chars.next()) {
+                            (Some(c), None) => Ok(c),
+                            _ => Err(ParseErr(format!("expected one character, got {:?}", s))),
+                        }
+                    };
+                    let score_of = |s: &str| {
+                        s.parse()
+                            .map_err(|_| ParseErr(format!("invalid score {:?}", s)))
+                    };
+                    language.pairs.push(Pair {
+                        open: char_of(open)?,
+                        close: char_of(close)?,
+                        illegal_score: score_of(illegal_score)?,
+                        ac_points: score_of(ac_points)?,
+                    });
+                }
+                ["ignore", chars] => language.ignored.extend(chars.chars()),
+                _ => return Err(ParseErr(format!("invalid definition {:?}", line))),
+            }
+        }
+        if language.pairs.len() > u8::MAX as usize + 1 {
+            return Err(ParseErr("too many pairs".to_string()));
+        }
+        Ok(language)
+    }
+}
+
+impl BracketLanguage {
+    fn opening(&self, c: char) -> Option<&Pair> {
+        self.pairs.iter().find(|p| p.open == c)
+    }
+
+    fn closing(&self, c: char) -> Option<&Pair> {
+        self.pairs.iter().find(|p| p.close == c)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorKind {
+    Mismatched { expected: char, found: char },
+    Unopened(char), // closing bracket with nothing open
+    Unknown(char),  // not a bracket and not ignored
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BracketError {
+    position: usize, // in chars
+    kind: ErrorKind,
+}
+
+#[derive(Debug)]
+struct Checked {
+    errors: Vec<BracketError>,
+    open: Vec<Pair>, // what's still open at the end, innermost last
+}
+
+impl Checked {
+    fn is_corrupted(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    fn completion(&self) -> String {
+        self.open.iter().rev().map(|p| p.close).collect()
+    }
+
+    fn ac_score(&self) -> u64 {
+        self.open
+            .iter()
+            .rev()
+            .fold(0, |score, p| score * 5 + p.ac_points)
+    }
+}
+
+// without recovery this stops at the first error, like a real parser would
+// with recovery, a closing bracket that matches something further down the stack closes everything
+// above it (as if the missing closing brackets were inserted), and anything else is deleted
+fn check(language: &BracketLanguage, line: &str, recover: bool) -> Checked {
+    let mut stack: Vec<Pair> = Vec::new();
+    let mut errors = Vec::new();
+    for (position, c) in line.chars().enumerate() {
+        if language.ignored.contains(&c) {
+            continue;
+        }
+        if let Some(pair) = language.opening(c) {
+            stack.push(*pair);
+            continue;
+        }
+        let kind = match (language.closing(c), stack.last()) {
+            (Some(pair), Some(top)) if pair.close == top.close => {
+                stack.pop();
+                continue;
+            }
+            (Some(_), Some(top)) => ErrorKind::Mismatched {
+                expected: top.close,
+                found: c,
+            },
+            (Some(_), None) => ErrorKind::Unopened(c),
+            (None, _) => ErrorKind::Unknown(c),
+        };
+        errors.push(BracketError { position, kind });
+        if !recover {
+            break;
+        }
+        if let Some(depth) = stack.iter().rposition(|p| p.close == c) {
+            stack.truncate(depth);
+        }
+    }
+    Checked {
+        errors,
+        open: stack,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Delete,             // drop the first char
+    Insert(Pair),       // give the first char a new partner
+    Match(usize, Pair), // pair the first char with the char at this index, substituting as needed
+}
+
+#[derive(Debug)]
+struct Repair {
+    line: String,
+    cost: usize,
+}
+
+// fewest insertions, deletions and substitutions that make the whole line balanced
+// cost[i][j] is the cheapest way to balance chars i..j, so this is O(n^3) in the line length
+// ignored chars are left out of the repaired line
+fn repair(language: &BracketLanguage, line: &str) -> Repair {
+    let chars: Vec<char> = line
+        .chars()
+        .filter(|c| !language.ignored.contains(c))
+        .collect();
+    let n = chars.len();
+
+    // cheapest pair to turn (a, b) into, counting one for each char that has to change
+    let best_pair = |a: char, b: char| {
+        language
+            .pairs
+            .iter()
+            .map(|p| ((a != p.open) as usize + (b != p.close) as usize, *p))
+            .min_by_key(|&(cost, _)| cost)
+    };
+
+    let mut cost = vec![vec![0; n + 1]; n + 1];
+    let mut steps = vec![vec![Step::Delete; n + 1]; n + 1];
+    for len in 1..=n {
+        for i in 0..=n - len {
+            let j = i + len;
+            // deleting a bracket and inserting a partner for it cost the same, but inserting keeps
+            // everything that was typed, so only chars that aren't brackets get deleted
+            let partner = language
+                .opening(chars[i])
+                .or_else(|| language.closing(chars[i]));
+            let mut best = match partner {
+                Some(pair) => (cost[i + 1][j] + 1, Step::Insert(*pair)),
+                None => (cost[i + 1][j] + 1, Step::Delete),
+            };
+            for k in i + 1..j {
+                if let Some((sub_cost, pair)) = best_pair(chars[i], chars[k]) {
+                    let total = sub_cost + cost[i + 1][k] + cost[k + 1][j];
+                    if total < best.0 {
+                        best = (total, Step::Match(k, pair));
+                    }
+                }
+            }
+            cost[i][j] = best.0;
+            steps[i][j] = best.1;
+        }
+    }
+
+    // rebuild the line by following the steps, with an explicit stack of ranges to fill
+    let mut out = String::new();
+    let mut todo = vec![Err((0, n))];
+    while let Some(item) = todo.pop() {
+        let (i, j) = match item {
+            Ok(c) => {
+                out.push(c);
+                continue;
+            }
+            Err(range) => range,
+        };
+        if i >= j {
+            continue;
+        }
+        match steps[i][j] {
+            Step::Delete => todo.push(Err((i + 1, j))),
+            Step::Insert(pair) if pair.open == chars[i] => {
+                todo.push(Ok(pair.close));
+                todo.push(Err((i + 1, j)));
+                todo.push(Ok(pair.open));
+            }
+            Step::Insert(pair) => {
+                todo.push(Err((i + 1, j)));
+                todo.push(Ok(pair.close));
+                todo.push(Ok(pair.open));
+            }
+            Step::Match(k, pair) => {
+                todo.push(Err((k + 1, j)));
+                todo.push(Ok(pair.close));
+                todo.push(Err((i + 1, k)));
+                todo.push(Ok(pair.open));
+            }
+        }
     }
+    Repair {
+        line: out,
+        cost: cost[0][n],
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    // the first error on a line, the rest of the line is skipped
+    Corrupted {
+        line: usize,
+        offset: u64, // bytes from the start of the stream
+        kind: ErrorKind,
+    },
+    // sent at the end of a line that still has open brackets
+    Incomplete {
+        line: usize,
+        offset: u64,
+        missing: usize,
+        ac_score: u64, // saturates instead of overflowing on very deep lines
+    },
+    // too many open brackets to keep track of, the rest of the line is skipped
+    TooDeep {
+        line: usize,
+        offset: u64,
+    },
 }
 
-fn get_ac_points(c: char) -> u64 {
-    match c {
-        ')' => 1,
-        ']' => 2,
-        '}' => 3,
-        '>' => 4,
-        _ => panic!("unexpected character '{}'", c),
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Corrupted { line, offset, kind } => {
+                write!(f, "line {} corrupted at byte {}: {:?}", line, offset, kind)
+            }
+            Event::Incomplete {
+                line,
+                offset,
+                missing,
+                ac_score,
+            } => write!(
+                f,
+                "line {} incomplete at byte {}: {} missing, score {}",
+                line, offset, missing, ac_score
+            ),
+            Event::TooDeep { line, offset } => {
+                write!(f, "line {} nested too deeply at byte {}", line, offset)
+            }
+        }
     }
 }
 
-fn get_closing(c: char) -> Option<char> {
-    match c {
-        '(' => Some(')'),
-        '[' => Some(']'),
-        '{' => Some('}'),
-        '<' => Some('>'),
-        _ => None,
+// checks input that's fed to it in chunks of any size, so a line never has to be in memory at once
+// memory only depends on how deeply nested a line is, which can be capped with max_depth
+struct StreamValidator<'a> {
+    language: &'a BracketLanguage,
+    max_depth: usize,
+    stack: Vec<u8>, // indexes into language.pairs
+    line: usize,
+    offset: u64,
+    skipping: bool,
+    utf8: Vec<u8>,    // bytes of a char that's been split between chunks
+    utf8_offset: u64, // where that char started
+}
+
+impl<'a> StreamValidator<'a> {
+    fn new(language: &'a BracketLanguage, max_depth: usize) -> Self {
+        StreamValidator {
+            language,
+            max_depth,
+            stack: Vec::new(),
+            line: 0,
+            offset: 0,
+            skipping: false,
+            utf8: Vec::with_capacity(4),
+            utf8_offset: 0,
+        }
+    }
+
+    fn feed(&mut self, bytes: &[u8], on_event: &mut impl FnMut(Event)) {
+        for &byte in bytes {
+            let offset = self.offset;
+            self.offset += 1;
+            // a byte that can't continue the buffered char cuts it short, and then starts over
+            if !self.utf8.is_empty() && !(0x80..=0xBF).contains(&byte) {
+                self.utf8.clear();
+                self.push_char(char::REPLACEMENT_CHARACTER, self.utf8_offset, on_event);
+            }
+            if self.utf8.is_empty() && byte.is_ascii() {
+                self.push_char(byte as char, offset, on_event);
+                continue;
+            }
+            if self.utf8.is_empty() {
+                self.utf8_offset = offset;
+            }
+            self.utf8.push(byte);
+            let expected = match self.utf8[0] {
+                0xC0..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                0xF0..=0xF7 => 4,
+                _ => 1, // not a valid start byte, so it'll turn into a replacement char
+            };
+            if self.utf8.len() == expected {
+                let c = std::str::from_utf8(&self.utf8)
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                    .unwrap_or(char::REPLACEMENT_CHARACTER);
+                self.utf8.clear();
+                self.push_char(c, self.utf8_offset, on_event);
+            }
+        }
+    }
+
+    // call at the end of the stream, in case the last line has no newline
+    fn finish(mut self, on_event: &mut impl FnMut(Event)) {
+        if !self.utf8.is_empty() {
+            self.utf8.clear();
+            self.push_char(char::REPLACEMENT_CHARACTER, self.utf8_offset, on_event);
+        }
+        self.end_line(on_event);
+    }
+
+    fn end_line(&mut self, on_event: &mut impl FnMut(Event)) {
+        if !self.skipping && !self.stack.is_empty() {
+            let pairs = &self.language.pairs;
+            on_event(Event::Incomplete {
+                line: self.line,
+                offset: self.offset,
+                missing: self.stack.len(),
+                ac_score: self.stack.iter().rev().fold(0_u64, |score, &p| {
+                    score
+                        .saturating_mul(5)
+                        .saturating_add(pairs[p as usize].ac_points)
+                }),
+            });
+        }
+        self.stack.clear();
+        self.skipping = false;
+        self.line += 1;
+    }
+
+    fn push_char(&mut self, c: char, offset: u64, on_event: &mut impl FnMut(Event)) {
+        if c == '\n' {
+            self.end_line(on_event);
+            return;
+        }
+        if self.skipping || c == '\r' || self.language.ignored.contains(&c) {
+            return;
+        }
+        let pairs = &self.language.pairs;
+        if let Some(index) = pairs.iter().position(|p| p.open == c) {
+            if self.stack.len() >= self.max_depth {
+                on_event(Event::TooDeep {
+                    line: self.line,
+                    offset,
+                });
+                self.skipping = true;
+            } else {
+                self.stack.push(index as u8);
+            }
+            return;
+        }
+        let kind = match (self.language.closing(c), self.stack.last()) {
+            (Some(pair), Some(&top)) if pair.close == pairs[top as usize].close => {
+                self.stack.pop();
+                return;
+            }
+            (Some(_), Some(&top)) => ErrorKind::Mismatched {
+                expected: pairs[top as usize].close,
+                found: c,
+            },
+            (Some(_), None) => ErrorKind::Unopened(c),
+            (None, _) => ErrorKind::Unknown(c),
+        };
+        on_event(Event::Corrupted {
+            line: self.line,
+            offset,
+            kind,
+        });
+        self.skipping = true;
     }
 }
 
-fn is_opening(c: char) -> bool {
-    get_closing(c).is_some()
+// prints events for whatever is piped into stdin, as soon as they're known
+fn stream_stdin(language: &BracketLanguage) {
+    let mut validator = StreamValidator::new(language, 1 << 24);
+    let mut stdin = io::stdin().lock();
+    let mut buf = [0; 8192];
+    let mut print = |event: Event| println!("{}", event);
+    loop {
+        match stdin.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => validator.feed(&buf[..n], &mut print),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => panic!("Failed to read stdin: {}", e),
+        }
+    }
+    validator.finish(&mut print);
 }
 
 fn main() {
-    let mut input = read_file("data/input.txt");
+    let input = read_file("data/input.txt");
+
+    // a different language can be passed as a file in the same format as STANDARD
+    // and with --stream, stdin is checked instead of the input
+    let args: Vec<String> = env::args().skip(1).collect();
+    let language: BracketLanguage = match args.iter().find(|a| !a.starts_with("--")) {
+        Some(path) => fs::read_to_string(path).expect("File not found"),
+        None => STANDARD.to_string(),
+    }
+    .parse()
+    .unwrap_or_else(|e: ParseErr| panic!("Invalid language: {}", e.0));
+    if args.iter().any(|a| a == "--stream") {
+        stream_stdin(&language);
+        return;
+    }
 
     // part 1
-    let mut stack = Vec::new();
     let mut error_score = 0;
-    input.retain(|line| {
-        stack.clear();
-        for c in line.chars() {
-            if is_opening(c) {
-                stack.push(c);
-            } else if let Some(pop_c) = stack.pop() {
-                if get_closing(pop_c).unwrap() != c {
-                    error_score += get_illegal_score(c);
-                    return false;
-                }
+    let mut incomplete = Vec::new();
+    for line in &input {
+        let checked = check(&language, line, false);
+        match checked.errors.first().map(|e| e.kind) {
+            Some(ErrorKind::Mismatched { found, .. }) | Some(ErrorKind::Unopened(found)) => {
+                error_score += language.closing(found).unwrap().illegal_score
             }
+            Some(ErrorKind::Unknown(_)) => (),
+            None => incomplete.push(checked),
         }
-        true
-    });
+    }
     println!("Part 1: error_score = {} points", error_score);
 
     // part 2
-    let mut ac_scores = Vec::new();
+    let mut ac_scores: Vec<u64> = incomplete.iter().map(Checked::ac_score).collect();
+    ac_scores.sort_unstable();
+    println!("Part 2: middle_score = {}", ac_scores[ac_scores.len() / 2]);
+
+    // with recovery, corrupted lines can have more than one error
+    let mut total_errors = 0;
+    let mut worst: Option<(usize, Checked)> = None;
+    for (i, line) in input.iter().enumerate() {
+        let checked = check(&language, line, true);
+        total_errors += checked.errors.len();
+        if checked.is_corrupted()
+            && worst
+                .as_ref()
+                .is_none_or(|(_, w)| checked.errors.len() > w.errors.len())
+        {
+            worst = Some((i, checked));
+        }
+    }
+    println!("{} errors in total with recovery", total_errors);
+    if let Some((i, checked)) = worst {
+        println!("Line {} has the most errors:", i);
+        for error in &checked.errors {
+            println!("  at {}: {:?}", error.position, error.kind);
+        }
+        println!("  and needs {:?} to complete", checked.completion());
+    }
+
+    // repair every corrupted line as cheaply as possible
+    let mut total_cost = 0;
+    let mut first = None;
     for line in &input {
-        stack.clear();
-        for c in line.chars() {
-            if is_opening(c) {
-                stack.push(c);
-            } else {
-                stack.pop();
+        if check(&language, line, false).is_corrupted() {
+            let repaired = repair(&language, line);
+            total_cost += repaired.cost;
+            first.get_or_insert((line, repaired));
+        }
+    }
+    println!("Repairing all corrupted lines takes {} edits", total_cost);
+    if let Some((line, repaired)) = first {
+        println!(
+            "eg. {} -> {} ({} edits)",
+            line, repaired.line, repaired.cost
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standard() -> BracketLanguage {
+        STANDARD.parse().unwrap()
+    }
+
+    fn check_repair(line: &str, cost: usize) -> String {
+        let language = standard();
+        let repaired = repair(&language, line);
+        assert_eq!(repaired.cost, cost, "{}", line);
+        let checked = check(&language, &repaired.line, false);
+        assert!(!checked.is_corrupted() && checked.open.is_empty());
+        repaired.line
+    }
+
+    #[test]
+    fn repair_balanced() {
+        assert_eq!(check_repair("", 0), "");
+        assert_eq!(check_repair("([]{<>})", 0), "([]{<>})");
+    }
+
+    #[test]
+    fn repair_inserts() {
+        assert_eq!(check_repair("(", 1), "()");
+        assert_eq!(check_repair(")", 1), "()");
+        assert_eq!(check_repair("[(])", 2), "[([])]");
+        assert_eq!(check_repair("((", 1), "()");
+    }
+
+    #[test]
+    fn repair_substitutes_and_deletes() {
+        check_repair("(]", 1);
+        check_repair("([)]", 2);
+        assert_eq!(check_repair("(x)", 1), "()");
+        assert_eq!(check_repair("x", 1), "");
+    }
+
+    #[test]
+    fn repair_example() {
+        let language = standard();
+        for line in read_file("data/example.txt") {
+            let repaired = repair(&language, &line);
+            let checked = check(&language, &repaired.line, false);
+            assert!(!checked.is_corrupted() && checked.open.is_empty());
+            // never worse than deleting everything
+            assert!(repaired.cost <= line.len());
+        }
+    }
+
+    fn stream(language: &BracketLanguage, bytes: &[u8], chunk_size: usize) -> Vec<Event> {
+        let mut validator = StreamValidator::new(language, 4);
+        let mut events = Vec::new();
+        let mut on_event = |event| events.push(event);
+        for chunk in bytes.chunks(chunk_size) {
+            validator.feed(chunk, &mut on_event);
+        }
+        validator.finish(&mut on_event);
+        events
+    }
+
+    // the streaming validator should agree with the line by line one, whatever the chunk size
+    #[test]
+    fn stream_agrees_with_check() {
+        let language = standard();
+        let bytes = fs::read("data/input.txt").unwrap();
+        let mut expected_score = 0;
+        let mut expected_ac = Vec::new();
+        for line in read_file("data/input.txt") {
+            let checked = check(&language, &line, false);
+            match checked.errors.first().map(|e| e.kind) {
+                Some(ErrorKind::Mismatched { found, .. }) => {
+                    expected_score += language.closing(found).unwrap().illegal_score
+                }
+                Some(_) => unreachable!(),
+                None => expected_ac.push(checked.ac_score()),
             }
         }
-        let mut line_score = 0;
-        for c in stack.iter().rev() {
-            line_score = line_score * 5 + get_ac_points(get_closing(*c).unwrap());
+        for chunk_size in [1, 7, 8192] {
+            let mut validator = StreamValidator::new(&language, usize::MAX);
+            let mut score = 0;
+            let mut ac = Vec::new();
+            let mut on_event = |event| match event {
+                Event::Corrupted {
+                    kind: ErrorKind::Mismatched { found, .. },
+                    ..
+                } => score += language.closing(found).unwrap().illegal_score,
+                Event::Incomplete { ac_score, .. } => ac.push(ac_score),
+                _ => panic!("unexpected {}", event),
+            };
+            for chunk in bytes.chunks(chunk_size) {
+                validator.feed(chunk, &mut on_event);
+            }
+            validator.finish(&mut on_event);
+            assert_eq!(score, expected_score);
+            assert_eq!(ac, expected_ac);
         }
-        ac_scores.push(line_score);
     }
-    ac_scores.sort_unstable();
-    println!("Part 2: middle_score = {}", ac_scores[ac_scores.len() / 2]);
+
+    #[test]
+    fn stream_empty_lines() {
+        let language = standard();
+        for chunk_size in [1, 3, 100] {
+            assert_eq!(
+                stream(&language, b"\n\n(\n\n", chunk_size),
+                [Event::Incomplete {
+                    line: 2,
+                    offset: 4,
+                    missing: 1,
+                    ac_score: 1,
+                }]
+            );
+            assert_eq!(stream(&language, b"", chunk_size), []);
+        }
+    }
+
+    #[test]
+    fn stream_truncated_utf8() {
+        let language = standard();
+        for chunk_size in [1, 2, 100] {
+            assert_eq!(
+                stream(&language, b"\xC3\n((", chunk_size),
+                [
+                    Event::Corrupted {
+                        line: 0,
+                        offset: 0,
+                        kind: ErrorKind::Unknown(char::REPLACEMENT_CHARACTER),
+                    },
+                    Event::Incomplete {
+                        line: 1,
+                        offset: 4,
+                        missing: 2,
+                        ac_score: 6,
+                    }
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn stream_multibyte_pairs() {
+        let language: BracketLanguage = "pair « » 1 1".parse().unwrap();
+        for chunk_size in [1, 3, 100] {
+            assert_eq!(
+                stream(&language, "«»\n«».»\n".as_bytes(), chunk_size),
+                [Event::Corrupted {
+                    line: 1,
+                    offset: 9,
+                    kind: ErrorKind::Unknown('.'),
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn stream_too_deep() {
+        let language = standard();
+        assert_eq!(
+            stream(&language, b"((((((\n()", 1),
+            [Event::TooDeep { line: 0, offset: 4 }]
+        );
+    }
 }
@@ -1,112 +1,350 @@
-// this one's a mess :)
 use std::{
+    collections::{HashMap, VecDeque},
+    env,
     fs::File,
     io::{prelude::*, BufReader},
-    num::TryFromIntError,
+    iter,
     path::Path,
+    str::FromStr,
 };
 
 #[derive(Debug)]
 struct ParseErr;
 
-impl From<TryFromIntError> for ParseErr {
-    fn from(_: TryFromIntError) -> Self {
-        ParseErr
-    }
+// how far part 2 and the cycle search simulate before giving up
+const MAX_STEPS: usize = 100_000;
+
+const EIGHT: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+const FOUR: [(isize, isize); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rules {
+    threshold: u8, // flashes once energy goes above this
+    reset: u8,     // energy after flashing
+    increment: u8, // added every step, and by every flashing neighbour
+    neighbourhood: Vec<(isize, isize)>,
+    wrap: bool, // neighbours wrap around the edges of the grid
 }
 
-#[derive(Debug, Clone, Copy)]
-struct Octopus {
-    energy: u8,
-    has_flashed: bool,
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            threshold: 9,
+            reset: 0,
+            increment: 1,
+            neighbourhood: EIGHT.to_vec(),
+            wrap: false,
+        }
+    }
 }
 
-impl TryFrom<char> for Octopus {
-    type Error = ParseErr;
+impl FromStr for Rules {
+    type Err = ParseErr;
 
-    fn try_from(value: char) -> Result<Self, Self::Error> {
-        Ok(Octopus {
-            energy: value.to_digit(10).ok_or(ParseErr)?.try_into()?,
-            has_flashed: false,
-        })
+    // eg. "threshold=5 reset=1 increment=2 neighbours=4 wrap", anything missing is the standard rule
+    // custom neighbourhoods are row,col offsets separated by semicolons, eg. "neighbours=-1,0;1,0"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Rules::default();
+        for token in s.split_whitespace() {
+            if token == "wrap" {
+                rules.wrap = true;
+                continue;
+            }
+            let (key, value) = token.split_once('=').ok_or(ParseErr)?;
+            match key {
+                "threshold" => rules.threshold = value.parse().map_err(|_| ParseErr)?,
+                "reset" => rules.reset = value.parse().map_err(|_| ParseErr)?,
+                "increment" => rules.increment = value.parse().map_err(|_| ParseErr)?,
+                "neighbours" => {
+                    rules.neighbourhood = match value {
+                        "8" => EIGHT.to_vec(),
+                        "4" => FOUR.to_vec(),
+                        _ => value
+                            .split(';')
+                            .map(|offset| {
+                                let (r, c) = offset.split_once(',').ok_or(ParseErr)?;
+                                let r = r.parse().map_err(|_| ParseErr)?;
+                                let c = c.parse().map_err(|_| ParseErr)?;
+                                Ok((r, c))
+                            })
+                            .collect::<Result<_, _>>()?,
+                    }
+                }
+                _ => return Err(ParseErr),
+            }
+        }
+        // energy is a u8 that saturates, so it could never get above 255, and nothing happens without an increment
+        if rules.threshold == u8::MAX || rules.increment == 0 {
+            return Err(ParseErr);
+        }
+        Ok(rules)
     }
 }
 
-fn read_file(filename: impl AsRef<Path>) -> Vec<Vec<Octopus>> {
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct OctopusGrid {
+    rows: usize,
+    cols: usize,
+    energy: Vec<u8>, // row-major
+    rules: Rules,
+}
+
+fn read_file(filename: impl AsRef<Path>) -> OctopusGrid {
     let file = File::open(filename).expect("File not found");
     let buf = BufReader::new(file);
-    buf.lines()
-        .map(|s| s.unwrap().chars().map(|c| c.try_into().unwrap()).collect())
-        .collect()
+    let mut rows = 0;
+    let mut energy = Vec::new();
+    for line in buf.lines() {
+        let line = line.expect("Failed to read line");
+        if line.trim().is_empty() {
+            continue;
+        }
+        energy.extend(
+            line.trim()
+                .chars()
+                .map(|c| c.to_digit(10).expect("Invalid energy level") as u8),
+        );
+        rows += 1;
+    }
+    let cols = energy.len() / rows;
+    assert_eq!(rows * cols, energy.len(), "All rows must be the same width");
+    OctopusGrid {
+        rows,
+        cols,
+        energy,
+        rules: Rules::default(),
+    }
 }
 
-fn get_adjacent(input: &[Vec<Octopus>], row_i: usize, col_i: usize) -> Vec<(usize, usize)> {
-    let mut output = Vec::new();
-    for i in -1..=1 {
-        for j in -1..=1 {
-            let adj_row_i = row_i as isize + i;
-            let adj_col_i = col_i as isize + j;
-            if (0..input.len() as isize).contains(&adj_row_i)
-                && (0..input[adj_row_i as usize].len() as isize).contains(&adj_col_i)
-            {
-                output.push((adj_row_i as usize, adj_col_i as usize))
+impl OctopusGrid {
+    // with wrap-around an octopus can be its own neighbour, or the same neighbour more than once,
+    // and it gets energy from each of them
+    fn get_adjacent(&self, row_i: usize, col_i: usize) -> Vec<(usize, usize)> {
+        let (rows, cols) = (self.rows as isize, self.cols as isize);
+        let mut output = Vec::new();
+        for &(i, j) in &self.rules.neighbourhood {
+            let mut adj_row_i = row_i as isize + i;
+            let mut adj_col_i = col_i as isize + j;
+            if self.rules.wrap {
+                adj_row_i = adj_row_i.rem_euclid(rows);
+                adj_col_i = adj_col_i.rem_euclid(cols);
+            } else if !(0..rows).contains(&adj_row_i) || !(0..cols).contains(&adj_col_i) {
+                continue;
             }
+            output.push((adj_row_i as usize, adj_col_i as usize))
         }
+        output
+    }
+
+    // advances one step, and returns every octopus that flashed in the order they flashed
+    // flashes spread through a queue instead of recursion, so big grids can't overflow the stack
+    fn step(&mut self) -> Vec<(usize, usize)> {
+        let mut has_flashed = vec![false; self.energy.len()];
+        let mut flashed = Vec::new();
+        let mut queue = VecDeque::new();
+        let Rules {
+            threshold,
+            reset,
+            increment,
+            ..
+        } = self.rules;
+        for (i, energy) in self.energy.iter_mut().enumerate() {
+            *energy = energy.saturating_add(increment);
+            if *energy > threshold {
+                has_flashed[i] = true;
+                queue.push_back((i / self.cols, i % self.cols));
+            }
+        }
+        while let Some((row_i, col_i)) = queue.pop_front() {
+            flashed.push((row_i, col_i));
+            for (adj_row_i, adj_col_i) in self.get_adjacent(row_i, col_i) {
+                let i = adj_row_i * self.cols + adj_col_i;
+                self.energy[i] = self.energy[i].saturating_add(increment);
+                if self.energy[i] > threshold && !has_flashed[i] {
+                    has_flashed[i] = true;
+                    queue.push_back((adj_row_i, adj_col_i));
+                }
+            }
+        }
+        for &(row_i, col_i) in &flashed {
+            self.energy[row_i * self.cols + col_i] = reset;
+        }
+        flashed
     }
-    output
-}
 
-fn try_flash(input: &mut [Vec<Octopus>], row_i: usize, col_i: usize) -> u32 {
-    let octopus = &mut input[row_i][col_i];
-    if octopus.has_flashed || octopus.energy <= 9 {
-        return 0;
+    // number of flashes on each step, forever
+    fn flashes(mut self) -> impl Iterator<Item = usize> {
+        iter::from_fn(move || Some(self.step().len()))
     }
-    octopus.has_flashed = true;
-    let mut flashes = 1;
-    for (adj_row_i, adj_col_i) in get_adjacent(input, row_i, col_i) {
-        input[adj_row_i][adj_col_i].energy += 1;
-        flashes += try_flash(input, adj_row_i, adj_col_i);
+
+    // simulates until the grid is in a state it's been in before, keeping every state it's seen
+    // None if that doesn't happen within max_steps, since some rules take a very long time to repeat
+    fn find_cycle(&self, max_steps: usize) -> Option<Cycle> {
+        let mut grid = self.clone();
+        let mut seen = HashMap::new();
+        let mut flashes = Vec::new();
+        while flashes.len() <= max_steps {
+            if let Some(&start) = seen.get(&grid.energy) {
+                return Some(Cycle {
+                    start,
+                    length: flashes.len() - start,
+                    flashes,
+                });
+            }
+            seen.insert(grid.energy.clone(), flashes.len());
+            flashes.push(grid.step().len());
+        }
+        None
+    }
+
+    // steps are numbered from 1, and None means the grid didn't sync within max_steps
+    fn first_sync_step(&self, max_steps: usize) -> Option<usize> {
+        let size = self.energy.len();
+        self.clone()
+            .flashes()
+            .take(max_steps)
+            .position(|n| n == size)
+            .map(|i| i + 1)
     }
-    flashes
 }
 
-fn main() {
-    let mut input = read_file("data/input.txt");
+// the state after start steps is the same as the state after start + length steps, so from then on
+// everything repeats
+#[derive(Debug)]
+struct Cycle {
+    start: usize,
+    length: usize,
+    flashes: Vec<usize>, // flashes on each step until the cycle closes, flashes[0] is step 1
+}
 
-    let mut total_flashes = 0;
-    let mut has_synced = false;
-    let mut step = 1;
-    loop {
-        for octopus in input.iter_mut().flatten() {
-            octopus.energy += 1;
+impl Cycle {
+    // step is numbered from 1, and nothing flashes before the first step
+    fn flashes_on(&self, step: u64) -> usize {
+        if step == 0 {
+            return 0;
         }
-        for row_i in 0..input.len() {
-            for col_i in 0..input[row_i].len() {
-                total_flashes += try_flash(&mut input, row_i, col_i);
-            }
+        let i = step - 1;
+        if i < self.flashes.len() as u64 {
+            self.flashes[i as usize]
+        } else {
+            let offset = (i - self.start as u64) % self.length as u64;
+            self.flashes[self.start + offset as usize]
         }
-        if step == 100 {
-            println!("Part 1: {} flashes", total_flashes);
-            if has_synced {
-                break;
-            }
-        }
-        let mut is_synced = true;
-        for octopus in input.iter_mut().flatten() {
-            if octopus.has_flashed {
-                octopus.has_flashed = false;
-                octopus.energy = 0;
-            } else {
-                is_synced = false;
-            }
+    }
+
+    // total flashes over the first steps steps
+    fn total_flashes(&self, steps: u64) -> u128 {
+        let before: u128 = self
+            .flashes
+            .iter()
+            .take(self.start)
+            .map(|&n| n as u128)
+            .sum();
+        if steps <= self.start as u64 {
+            return self.flashes[..steps as usize]
+                .iter()
+                .map(|&n| n as u128)
+                .sum();
         }
-        if is_synced && !has_synced {
-            println!("Part 2: synced on step {}", step);
-            if step >= 100 {
-                break;
+        let cycle = &self.flashes[self.start..];
+        let per_cycle: u128 = cycle.iter().map(|&n| n as u128).sum();
+        let remaining = steps - self.start as u64;
+        let full = (remaining / self.length as u64) as u128;
+        let partial: u128 = cycle[..(remaining % self.length as u64) as usize]
+            .iter()
+            .map(|&n| n as u128)
+            .sum();
+        before + full * per_cycle + partial
+    }
+}
+
+fn main() {
+    let mut input = read_file("data/input.txt");
+
+    // the rules can be passed as arguments, eg. "threshold=5 neighbours=4 wrap"
+    input.rules = env::args()
+        .skip(1)
+        .collect::<Vec<_>>()
+        .join(" ")
+        .parse()
+        .expect("Invalid rules");
+
+    // part 1
+    println!(
+        "Part 1: {} flashes",
+        input.clone().flashes().take(100).sum::<usize>()
+    );
+
+    // part 2
+    match input.first_sync_step(MAX_STEPS) {
+        Some(step) => println!("Part 2: synced on step {}", step),
+        None => println!("Part 2: didn't sync within {} steps", MAX_STEPS),
+    }
+
+    // the cycle is only needed for looking further ahead than can be simulated
+    let steps = 1_000_000_000;
+    match input.find_cycle(MAX_STEPS) {
+        Some(cycle) => println!(
+            "Cycle of length {} starts after step {}, {} flashes after {} steps, {} on the last step",
+            cycle.length,
+            cycle.start,
+            cycle.total_flashes(steps),
+            steps,
+            cycle.flashes_on(steps)
+        ),
+        None => println!("No cycle within {} steps", MAX_STEPS),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example() {
+        let input = read_file("data/example.txt");
+        assert_eq!(input.clone().flashes().take(10).sum::<usize>(), 204);
+        assert_eq!(input.clone().flashes().take(100).sum::<usize>(), 1656);
+        assert_eq!(input.first_sync_step(1000), Some(195));
+        assert_eq!(input.first_sync_step(100), None);
+    }
+
+    // the cycle should give the same answers as just simulating
+    #[test]
+    fn cycle_matches_simulation() {
+        for rules in [
+            "",
+            "threshold=5 neighbours=4 wrap",
+            "increment=3 reset=2 neighbours=-1,0;1,0;0,2",
+        ] {
+            let mut input = read_file("data/example.txt");
+            input.rules = rules.parse().unwrap();
+            let cycle = input.find_cycle(MAX_STEPS).unwrap();
+            let simulated: Vec<usize> = input.clone().flashes().take(1000).collect();
+            let mut total = 0;
+            assert_eq!(cycle.total_flashes(0), 0);
+            assert_eq!(cycle.flashes_on(0), 0);
+            for (i, &n) in simulated.iter().enumerate() {
+                total += n as u128;
+                assert_eq!(cycle.flashes_on(i as u64 + 1), n);
+                assert_eq!(cycle.total_flashes(i as u64 + 1), total);
             }
-            has_synced = true;
         }
-        step += 1;
+    }
+
+    #[test]
+    fn cycle_not_found() {
+        let input = read_file("data/example.txt");
+        assert!(input.find_cycle(10).is_none());
+        assert!(input.find_cycle(1000).is_some());
     }
 }
@@ -1,5 +1,5 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     fs::File,
     io::{prelude::*, BufReader},
     path::Path,
@@ -13,11 +13,31 @@ enum NodeKind {
     Large(String),
 }
 
-fn read_file(filename: impl AsRef<Path>) -> (HashSet<NodeKind>, HashMap<NodeKind, Vec<NodeKind>>) {
+impl NodeKind {
+    fn name(&self) -> &str {
+        match self {
+            NodeKind::Start => "start",
+            NodeKind::End => "end",
+            NodeKind::Small(name) | NodeKind::Large(name) => name,
+        }
+    }
+}
+
+// nodes are referred to by their index, and small caves also get an index into the visit mask
+#[derive(Debug)]
+struct CaveGraph {
+    nodes: Vec<NodeKind>,
+    edges: Vec<Vec<usize>>,
+    small_index: Vec<Option<usize>>,
+    start: usize,
+}
+
+fn read_file(filename: impl AsRef<Path>) -> CaveGraph {
     let file = File::open(filename).expect("File not found");
     let mut lines = BufReader::new(file).lines();
-    let mut nodes = HashSet::new();
-    let mut edges = HashMap::new();
+    let mut indices = HashMap::new();
+    let mut nodes = Vec::new();
+    let mut edges: Vec<Vec<usize>> = Vec::new();
     while let Some(Ok(line)) = lines.next() {
         let line_nodes = line
             .split('-')
@@ -29,75 +49,327 @@ fn read_file(filename: impl AsRef<Path>) -> (HashSet<NodeKind>, HashMap<NodeKind
                     false => NodeKind::Large(s.to_string()),
                 },
             })
+            .map(|node| {
+                *indices.entry(node.clone()).or_insert_with(|| {
+                    nodes.push(node);
+                    edges.push(Vec::new());
+                    nodes.len() - 1
+                })
+            })
             .collect::<Vec<_>>();
-        nodes.insert(line_nodes[0].clone());
-        nodes.insert(line_nodes[1].clone());
-        edges
-            .entry(line_nodes[0].clone())
-            .or_insert_with(Vec::new)
-            .push(line_nodes[1].clone());
-        edges
-            .entry(line_nodes[1].clone())
-            .or_insert_with(Vec::new)
-            .push(line_nodes[0].clone());
-    }
-    (nodes, edges)
-}
+        edges[line_nodes[0]].push(line_nodes[1]);
+        edges[line_nodes[1]].push(line_nodes[0]);
+    }
 
-fn traverse(
-    nodes: &HashSet<NodeKind>,
-    edges: &HashMap<NodeKind, Vec<NodeKind>>,
-    current: &NodeKind,
-    visited_small: HashSet<NodeKind>,
-    has_double_visited: bool,
-) -> u32 {
-    let mut paths = 0;
-    if let Some(adjacent_nodes) = edges.get(current) {
-        for node in adjacent_nodes {
-            let mut has_double_visited = has_double_visited;
-            if visited_small.contains(node) {
-                if has_double_visited {
-                    continue;
-                } else {
-                    has_double_visited = true;
-                }
+    // two connected large caves could be bounced between forever
+    for (i, node) in nodes.iter().enumerate() {
+        if let NodeKind::Large(_) = node {
+            assert!(
+                edges[i]
+                    .iter()
+                    .all(|&j| !matches!(nodes[j], NodeKind::Large(_))),
+                "Infinitely many paths through {}",
+                node.name()
+            );
+        }
+    }
+
+    let mut small_count = 0;
+    let small_index = nodes
+        .iter()
+        .map(|node| match node {
+            NodeKind::Small(_) => {
+                small_count += 1;
+                Some(small_count - 1)
             }
-            paths += match node {
+            _ => None,
+        })
+        .collect();
+    CaveGraph {
+        start: *indices.get(&NodeKind::Start).expect("No start cave"),
+        nodes,
+        edges,
+        small_index,
+    }
+}
+
+// how often small caves can be visited more than once
+#[derive(Debug, Clone, Copy)]
+enum RevisitPolicy {
+    Total(u32),   // k revisits shared between all small caves
+    PerCave(u32), // every small cave can be revisited k times
+}
+
+impl RevisitPolicy {
+    // bits needed per small cave in the visit mask
+    // a total budget only needs to know if a cave has been visited, the budget counts the rest
+    fn width(&self) -> u32 {
+        match *self {
+            RevisitPolicy::Total(_) => 1,
+            RevisitPolicy::PerCave(k) => u32::BITS - (k + 1).leading_zeros(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+struct State {
+    node: usize,
+    visits: u64, // visit count of every small cave, packed policy.width() bits each
+    budget: u32, // revisits left for a total policy
+}
+
+impl CaveGraph {
+    fn initial_state(&self, policy: RevisitPolicy) -> State {
+        let small_count = self.small_index.iter().flatten().count() as u32;
+        assert!(
+            small_count * policy.width() <= u64::BITS,
+            "Too many small caves for the visit mask"
+        );
+        State {
+            node: self.start,
+            visits: 0,
+            budget: match policy {
+                RevisitPolicy::Total(k) => k,
+                RevisitPolicy::PerCave(_) => 0,
+            },
+        }
+    }
+
+    // the state after moving to node, or None if the policy doesn't allow it
+    // start can't be re-entered and end finishes the path, so neither is handled here
+    fn enter(&self, policy: RevisitPolicy, state: State, node: usize) -> Option<State> {
+        let Some(small_i) = self.small_index[node] else {
+            return Some(State { node, ..state });
+        };
+        let width = policy.width();
+        let shift = small_i as u32 * width;
+        let count = (state.visits >> shift) & ((1 << width) - 1);
+        match policy {
+            RevisitPolicy::Total(_) if count == 0 => Some(State {
+                node,
+                visits: state.visits | 1 << shift,
+                budget: state.budget,
+            }),
+            RevisitPolicy::Total(_) if state.budget > 0 => Some(State {
+                node,
+                budget: state.budget - 1,
+                ..state
+            }),
+            RevisitPolicy::PerCave(k) if count <= k as u64 => Some(State {
+                node,
+                visits: state.visits + (1 << shift),
+                budget: state.budget,
+            }),
+            _ => None,
+        }
+    }
+
+    // the number of paths only depends on the state, so each one is only counted once
+    fn count_from(
+        &self,
+        policy: RevisitPolicy,
+        state: State,
+        memo: &mut HashMap<State, u64>,
+    ) -> u64 {
+        if let Some(&paths) = memo.get(&state) {
+            return paths;
+        }
+        let mut paths = 0;
+        for &next in &self.edges[state.node] {
+            paths += match self.nodes[next] {
                 NodeKind::Start => 0,
                 NodeKind::End => 1,
+                _ => match self.enter(policy, state, next) {
+                    Some(next_state) => self.count_from(policy, next_state, memo),
+                    None => 0,
+                },
+            };
+        }
+        memo.insert(state, paths);
+        paths
+    }
+
+    fn count_paths(&self, policy: RevisitPolicy) -> u64 {
+        self.count_from(policy, self.initial_state(policy), &mut HashMap::new())
+    }
+
+    fn paths(&self, policy: RevisitPolicy) -> Paths<'_> {
+        Paths {
+            graph: self,
+            policy,
+            stack: vec![(self.initial_state(policy), 0)],
+        }
+    }
+}
+
+// depth first search with an explicit stack, so paths are only found as they're asked for
+struct Paths<'a> {
+    graph: &'a CaveGraph,
+    policy: RevisitPolicy,
+    stack: Vec<(State, usize)>, // the state at each node on the path, and the next edge to try
+}
+
+impl<'a> Iterator for Paths<'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let graph = self.graph;
+        while let Some(&(state, edge_i)) = self.stack.last() {
+            let Some(&next) = graph.edges[state.node].get(edge_i) else {
+                self.stack.pop();
+                continue;
+            };
+            self.stack.last_mut().unwrap().1 += 1;
+            match graph.nodes[next] {
+                NodeKind::Start => (),
+                NodeKind::End => {
+                    let mut path: Vec<_> = self
+                        .stack
+                        .iter()
+                        .map(|(state, _)| graph.nodes[state.node].name())
+                        .collect();
+                    path.push(graph.nodes[next].name());
+                    return Some(path);
+                }
                 _ => {
-                    let mut visited_small = visited_small.clone();
-                    if let NodeKind::Small(_) = node {
-                        visited_small.insert(node.clone());
+                    if let Some(next_state) = graph.enter(self.policy, state, next) {
+                        self.stack.push((next_state, 0));
                     }
-                    traverse(nodes, edges, node, visited_small, has_double_visited)
                 }
-            };
+            }
         }
+        None
     }
-    paths
 }
 
 fn solve(filename: &str) {
     println!("{}:", filename);
-    let (nodes, edges) = read_file(filename);
+    let graph = read_file(filename);
 
     // part 1
     println!(
         "\tPart 1: {} paths",
-        traverse(&nodes, &edges, &NodeKind::Start, HashSet::new(), true)
+        graph.count_paths(RevisitPolicy::Total(0))
     );
 
     // part 2
     println!(
         "\tPart 2: {} paths",
-        traverse(&nodes, &edges, &NodeKind::Start, HashSet::new(), false)
+        graph.count_paths(RevisitPolicy::Total(1))
+    );
+
+    println!(
+        "\t{} paths with 2 revisits in total, {} with every small cave revisited at most once",
+        graph.count_paths(RevisitPolicy::Total(2)),
+        graph.count_paths(RevisitPolicy::PerCave(1))
     );
 }
 
 fn main() {
+    // the paths themselves are only listed for the smallest example
+    let graph = read_file("data/example1.txt");
+    for path in graph.paths(RevisitPolicy::Total(0)) {
+        println!("{}", path.join(","));
+    }
+
     solve("data/example1.txt");
     solve("data/example2.txt");
     solve("data/example3.txt");
     solve("data/input.txt");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const EXAMPLES: [&str; 3] = [
+        "data/example1.txt",
+        "data/example2.txt",
+        "data/example3.txt",
+    ];
+
+    const POLICIES: [RevisitPolicy; 6] = [
+        RevisitPolicy::Total(0),
+        RevisitPolicy::Total(1),
+        RevisitPolicy::Total(2),
+        RevisitPolicy::PerCave(0),
+        RevisitPolicy::PerCave(1),
+        RevisitPolicy::PerCave(2),
+    ];
+
+    // checks a path from scratch, without going through the visit mask
+    fn allowed(graph: &CaveGraph, path: &[&str], policy: RevisitPolicy) -> bool {
+        let mut visits: HashMap<&str, u32> = HashMap::new();
+        for name in path {
+            if graph
+                .nodes
+                .iter()
+                .any(|n| matches!(n, NodeKind::Small(s) if s == name))
+            {
+                *visits.entry(name).or_insert(0) += 1;
+            }
+        }
+        let revisits = visits.values().map(|&n| n - 1);
+        match policy {
+            RevisitPolicy::Total(k) => revisits.sum::<u32>() <= k,
+            RevisitPolicy::PerCave(k) => revisits.max().unwrap_or(0) <= k,
+        }
+    }
+
+    #[test]
+    fn examples() {
+        let expected = [(10, 36), (19, 103), (226, 3509)];
+        for (filename, (part_1, part_2)) in EXAMPLES.iter().zip(expected) {
+            let graph = read_file(filename);
+            assert_eq!(graph.count_paths(RevisitPolicy::Total(0)), part_1);
+            assert_eq!(graph.count_paths(RevisitPolicy::Total(1)), part_2);
+        }
+    }
+
+    // the lazy paths and the memoized count should agree, and every path should be a real one
+    #[test]
+    fn paths_match_count() {
+        for filename in &EXAMPLES[..2] {
+            let graph = read_file(filename);
+            for policy in POLICIES {
+                let paths: Vec<Vec<&str>> = graph.paths(policy).collect();
+                assert_eq!(paths.len() as u64, graph.count_paths(policy));
+                let unique: HashSet<_> = paths.iter().collect();
+                assert_eq!(unique.len(), paths.len());
+                for path in &paths {
+                    assert_eq!(path.first(), Some(&"start"));
+                    assert_eq!(path.last(), Some(&"end"));
+                    assert!(allowed(&graph, path, policy), "{:?} {:?}", policy, path);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn per_cave_budgets() {
+        for filename in EXAMPLES {
+            let graph = read_file(filename);
+            // no revisits is the same either way
+            assert_eq!(
+                graph.count_paths(RevisitPolicy::PerCave(0)),
+                graph.count_paths(RevisitPolicy::Total(0))
+            );
+            // revisiting every cave once allows at least as much as one revisit in total
+            assert!(
+                graph.count_paths(RevisitPolicy::PerCave(1))
+                    >= graph.count_paths(RevisitPolicy::Total(1))
+            );
+            assert!(
+                graph.count_paths(RevisitPolicy::PerCave(2))
+                    > graph.count_paths(RevisitPolicy::PerCave(1))
+            );
+        }
+        // two revisits each means a cave can be visited three times
+        let graph = read_file("data/example1.txt");
+        let paths: Vec<_> = graph.paths(RevisitPolicy::PerCave(2)).collect();
+        assert!(paths
+            .iter()
+            .any(|p| p.iter().filter(|&&n| n == "b").count() == 3));
+    }
+}
@@ -1,87 +1,372 @@
 use std::{
+    cmp::Ordering,
     fs::File,
     io::{prelude::*, BufReader},
     path::Path,
 };
 
-fn read_file(filename: impl AsRef<Path>) -> Vec<Vec<u8>> {
+// each line is packed into a u128, with the first char of the line as the most significant bit
+#[derive(Debug, Clone)]
+struct Report {
+    width: usize,
+    lines: Vec<u128>,
+}
+
+impl Report {
+    // mask for the bit at index i, counting from the left like the input does
+    fn mask(&self, i: usize) -> u128 {
+        1 << (self.width - 1 - i)
+    }
+}
+
+fn read_file(filename: impl AsRef<Path>) -> Report {
     let file = File::open(filename).expect("File not found");
-    let buf = BufReader::new(file);
-    buf.lines()
-        .map(|line| {
-            line.expect("Failed to read line")
-                .chars()
-                .filter_map(|c| c.to_digit(2).map(|u| u as u8))
-                .collect()
+    let mut buf = BufReader::new(file);
+    let mut contents = String::new();
+    buf.read_to_string(&mut contents)
+        .expect("Failed to read file");
+    parse(&contents)
+}
+
+fn parse(contents: &str) -> Report {
+    let mut width = 0;
+    let lines = contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() {
+                return None;
+            }
+            if width == 0 {
+                width = line.len();
+                assert!(width <= 128, "Lines can be at most 128 bits wide");
+            }
+            assert_eq!(line.len(), width, "All lines must be the same width");
+            Some(u128::from_str_radix(line, 2).expect("Failed to parse line"))
         })
-        .collect()
+        .collect();
+    Report { width, lines }
+}
+
+// number of lines with the given bit set
+fn count_ones(lines: &[u128], mask: u128) -> usize {
+    lines.iter().filter(|&&line| line & mask != 0).count()
+}
+
+// moves all lines whose bit matches keep_bit to the front, and returns how many there are
+fn partition(lines: &mut [u128], mask: u128, keep_bit: u8) -> usize {
+    let mut kept = 0;
+    for i in 0..lines.len() {
+        if (lines[i] & mask != 0) == (keep_bit == 1) {
+            lines.swap(kept, i);
+            kept += 1;
+        }
+    }
+    kept
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BitCriterion {
+    MostCommon { tie: u8 }, // keep the most common bit, or tie if there are equal amounts
+    LeastCommon { tie: u8 }, // keep the least common bit, or tie if there are equal amounts
+}
+
+trait Criterion {
+    // take number of zeros and number of ones, return 0 or 1
+    fn keep_bit(&self, num_0: usize, num_1: usize) -> u8;
+}
+
+impl Criterion for BitCriterion {
+    fn keep_bit(&self, num_0: usize, num_1: usize) -> u8 {
+        match (*self, num_0.cmp(&num_1)) {
+            (BitCriterion::MostCommon { tie }, Ordering::Equal)
+            | (BitCriterion::LeastCommon { tie }, Ordering::Equal) => tie,
+            (BitCriterion::MostCommon { .. }, Ordering::Less)
+            | (BitCriterion::LeastCommon { .. }, Ordering::Greater) => 1,
+            _ => 0,
+        }
+    }
 }
 
-#[allow(clippy::ptr_arg)]
-fn filter<F>(input: &Vec<Vec<u8>>, f: F) -> Option<u32>
+// so any closure can be used as a custom criterion
+impl<F> Criterion for F
 where
-    F: Fn(u32, u32) -> u8, // take number of zeros and number of ones, return 0 or 1
+    F: Fn(usize, usize) -> u8,
 {
-    let mut working = input.clone();
-    let len = working[0].len();
-    let mut counts = vec![0, 0];
-    for bit_num in 0..len {
-        for line in &working {
-            counts[line[bit_num] as usize] += 1;
+    fn keep_bit(&self, num_0: usize, num_1: usize) -> u8 {
+        self(num_0, num_1)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TraceStep {
+    bit_num: usize,
+    num_0: usize,
+    num_1: usize,
+    keep_bit: u8,
+    survivors: Vec<u128>,
+}
+
+// reorders lines in place while filtering
+// if trace is given, a step is pushed to it for every bit position that was checked
+fn filter(
+    report: &Report,
+    lines: &mut [u128],
+    criterion: impl Criterion,
+    mut trace: Option<&mut Vec<TraceStep>>,
+) -> Option<u128> {
+    let mut working = lines;
+    for bit_num in 0..report.width {
+        let mask = report.mask(bit_num);
+        let num_1 = count_ones(working, mask);
+        let num_0 = working.len() - num_1;
+        let keep_bit = criterion.keep_bit(num_0, num_1);
+        let kept = partition(working, mask, keep_bit);
+        working = &mut working[..kept];
+        if let Some(trace) = trace.as_mut() {
+            trace.push(TraceStep {
+                bit_num,
+                num_0,
+                num_1,
+                keep_bit,
+                survivors: working.to_vec(),
+            });
         }
-        let keep_bit = f(counts[0], counts[1]);
-        working = working
-            .iter()
-            .filter(|l| l[bit_num] == keep_bit)
-            .cloned()
-            .collect();
         if working.len() == 1 {
-            return Some(working[0].iter().fold(0, |acc, &b| (acc << 1) | b as u32));
+            return Some(working[0]);
         }
-        counts.clear();
-        counts.resize(2, 0);
     }
     None
 }
 
-fn main() {
-    let input = read_file("data/input.txt");
-
-    // part 1
-    let mut counts = vec![[0, 0]; input[0].len()];
-    for line in &input {
-        for (i, bit) in line.iter().enumerate() {
-            counts[i][*bit as usize] += 1;
+fn print_trace(name: &str, report: &Report, trace: &[TraceStep]) {
+    println!("{} trace:", name);
+    for step in trace {
+        print!(
+            "  bit {:>3}: {} zeros, {} ones, kept {} -> {} lines",
+            step.bit_num,
+            step.num_0,
+            step.num_1,
+            step.keep_bit,
+            step.survivors.len()
+        );
+        if step.survivors.len() <= 4 {
+            let survivors: Vec<_> = step
+                .survivors
+                .iter()
+                .map(|line| format!("{:0width$b}", line, width = report.width))
+                .collect();
+            print!(" ({})", survivors.join(", "));
         }
+        println!();
     }
+}
 
-    let mut gamma: u32 = 0;
-    let mut epsilon: u32 = 0;
-    for count in &counts {
-        let (most_common, least_common) = if count[0] <= count[1] {
-            (1, 0) // 1 is most common, 0 is least
-        } else {
-            (0, 1)
-        };
-        gamma = (gamma << 1) | most_common;
-        epsilon = (epsilon << 1) | least_common;
+// a product of two values over 64 bits wide might not fit in a u128
+fn format_product(a: u128, b: u128) -> String {
+    match a.checked_mul(b) {
+        Some(product) => product.to_string(),
+        None => "too big for 128 bits".to_string(),
     }
+}
+
+// gamma takes the most common bit of each column, and epsilon the least common
+fn power_rates(report: &Report) -> (u128, u128) {
+    let mut gamma: u128 = 0;
+    let mut epsilon: u128 = 0;
+    for bit_num in 0..report.width {
+        let num_1 = count_ones(&report.lines, report.mask(bit_num));
+        let num_0 = report.lines.len() - num_1;
+        let most_common = BitCriterion::MostCommon { tie: 1 }.keep_bit(num_0, num_1);
+        let least_common = BitCriterion::LeastCommon { tie: 0 }.keep_bit(num_0, num_1);
+        gamma = (gamma << 1) | most_common as u128;
+        epsilon = (epsilon << 1) | least_common as u128;
+    }
+    (gamma, epsilon)
+}
+
+fn main() {
+    let input = read_file("data/input.txt");
+
+    // part 1
+    let (gamma, epsilon) = power_rates(&input);
     println!(
         "Part 1: gamma = {}, epsilon = {}, product = {}",
         gamma,
         epsilon,
-        gamma * epsilon
+        format_product(gamma, epsilon)
     );
 
     // part 2
-    let o2_rating = filter(&input, |num_0, num_1| if num_1 >= num_0 { 1 } else { 0 })
-        .expect("Failed to find a value for o2");
-    let co2_rating = filter(&input, |num_0, num_1| if num_0 <= num_1 { 0 } else { 1 })
-        .expect("Failed to find a value for co2");
+    let mut lines = input.lines.clone();
+    let mut o2_trace = Vec::new();
+    let o2_rating = filter(
+        &input,
+        &mut lines,
+        BitCriterion::MostCommon { tie: 1 },
+        Some(&mut o2_trace),
+    )
+    .expect("Failed to find a value for o2");
+    lines.copy_from_slice(&input.lines);
+    let mut co2_trace = Vec::new();
+    let co2_rating = filter(
+        &input,
+        &mut lines,
+        BitCriterion::LeastCommon { tie: 0 },
+        Some(&mut co2_trace),
+    )
+    .expect("Failed to find a value for co2");
     println!(
         "Part 2: o2_rating = {}, co2_rating = {}, product = {}",
         o2_rating,
         co2_rating,
-        o2_rating * co2_rating
+        format_product(o2_rating, co2_rating)
     );
+    print_trace("o2", &input, &o2_trace);
+    print_trace("co2", &input, &co2_trace);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ratings(report: &Report) -> (u128, u128) {
+        let mut lines = report.lines.clone();
+        let o2 = filter(
+            report,
+            &mut lines,
+            BitCriterion::MostCommon { tie: 1 },
+            None,
+        )
+        .unwrap();
+        let mut lines = report.lines.clone();
+        let co2 = filter(
+            report,
+            &mut lines,
+            BitCriterion::LeastCommon { tie: 0 },
+            None,
+        )
+        .unwrap();
+        (o2, co2)
+    }
+
+    #[test]
+    fn example() {
+        let report = read_file("data/example.txt");
+        assert_eq!(report.width, 5);
+        assert_eq!(power_rates(&report), (22, 9));
+        assert_eq!(ratings(&report), (23, 10));
+    }
+
+    #[test]
+    fn width_128() {
+        let report = parse(&format!(
+            "{}\n{}\n{}\n",
+            "1".repeat(128),
+            "0".repeat(127) + "1",
+            "1".to_string() + &"0".repeat(127)
+        ));
+        assert_eq!(report.width, 128);
+        assert_eq!(report.lines, [u128::MAX, 1, 1 << 127]);
+        assert_eq!(report.mask(0), 1 << 127);
+        assert_eq!(report.mask(127), 1);
+        let gamma = 1 << 127 | 1;
+        assert_eq!(power_rates(&report), (gamma, !gamma));
+        assert_eq!(format_product(gamma, !gamma), "too big for 128 bits");
+        assert_eq!(ratings(&report), (u128::MAX, 1));
+    }
+
+    #[test]
+    fn count_ones_many_lines() {
+        // more lines than fit in one 128 bit chunk, and not a multiple of it either
+        let lines: Vec<u128> = (0..1000).collect();
+        for bit in 0..10 {
+            let expected = (0..1000_u128).filter(|n| n >> bit & 1 == 1).count();
+            assert_eq!(count_ones(&lines, 1 << bit), expected);
+        }
+        assert_eq!(count_ones(&lines, 1 << 127), 0);
+        assert_eq!(count_ones(&[], 1), 0);
+    }
+
+    #[test]
+    fn partition_in_place() {
+        let mut lines: Vec<u128> = (0..20).collect();
+        let kept = partition(&mut lines, 0b100, 1);
+        assert_eq!(kept, 8);
+        assert!(lines[..kept].iter().all(|&n| n & 0b100 != 0));
+        assert!(lines[kept..].iter().all(|&n| n & 0b100 == 0));
+        lines.sort_unstable();
+        assert_eq!(lines, (0..20).collect::<Vec<_>>());
+
+        let kept = partition(&mut lines, 0b100, 0);
+        assert_eq!(kept, 12);
+        assert!(lines[..kept].iter().all(|&n| n & 0b100 == 0));
+    }
+
+    #[test]
+    fn tie_breaking() {
+        for tie in [0, 1] {
+            assert_eq!(BitCriterion::MostCommon { tie }.keep_bit(3, 3), tie);
+            assert_eq!(BitCriterion::LeastCommon { tie }.keep_bit(3, 3), tie);
+        }
+        assert_eq!(BitCriterion::MostCommon { tie: 0 }.keep_bit(2, 5), 1);
+        assert_eq!(BitCriterion::MostCommon { tie: 1 }.keep_bit(5, 2), 0);
+        assert_eq!(BitCriterion::LeastCommon { tie: 0 }.keep_bit(2, 5), 0);
+        assert_eq!(BitCriterion::LeastCommon { tie: 1 }.keep_bit(5, 2), 1);
+
+        // the example ends on a tie for both ratings, so flipping the preference changes them
+        let report = read_file("data/example.txt");
+        let mut lines = report.lines.clone();
+        let o2 = filter(
+            &report,
+            &mut lines,
+            BitCriterion::MostCommon { tie: 0 },
+            None,
+        );
+        assert_eq!(o2, Some(0b10110));
+        let mut lines = report.lines.clone();
+        let co2 = filter(
+            &report,
+            &mut lines,
+            BitCriterion::LeastCommon { tie: 1 },
+            None,
+        );
+        assert_eq!(co2, Some(0b01111));
+    }
+
+    #[test]
+    fn closure_criterion() {
+        // keeping ones whenever there are any finds the biggest line
+        let report = read_file("data/example.txt");
+        let mut lines = report.lines.clone();
+        let biggest = filter(&report, &mut lines, |_, num_1| (num_1 > 0) as u8, None);
+        assert_eq!(biggest, report.lines.iter().max().copied());
+    }
+
+    #[test]
+    fn trace() {
+        let report = read_file("data/example.txt");
+        let mut lines = report.lines.clone();
+        let mut trace = Vec::new();
+        let o2 = filter(
+            &report,
+            &mut lines,
+            BitCriterion::MostCommon { tie: 1 },
+            Some(&mut trace),
+        )
+        .unwrap();
+        assert_eq!(trace.len(), 5);
+        for (i, step) in trace.iter().enumerate() {
+            assert_eq!(step.bit_num, i);
+            assert!(step
+                .survivors
+                .iter()
+                .all(|&line| (line & report.mask(i) != 0) == (step.keep_bit == 1)));
+        }
+        assert_eq!(
+            trace.iter().map(|s| (s.num_0, s.num_1)).collect::<Vec<_>>(),
+            [(5, 7), (4, 3), (1, 3), (1, 2), (1, 1)]
+        );
+        assert_eq!(trace.last().unwrap().survivors, [o2]);
+    }
 }
@@ -1,162 +1,586 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    env, fmt,
     fs::File,
     io::{prelude::*, BufReader},
     path::Path,
+    str::FromStr,
 };
 
+#[derive(Debug)]
+struct ParseErr(String);
+
 #[derive(Clone, Copy, Debug)]
 struct Number {
-    value: u8,
+    value: u32,
     marked: bool,
 }
 
-type Board = [[Number; 5]; 5];
+#[derive(Clone, Debug)]
+struct Board {
+    rows: usize,
+    cols: usize,
+    numbers: Vec<Number>, // row-major, so cell = row * cols + col
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum WinPattern {
+    Rows,
+    Columns,
+    Diagonals,
+    FourCorners,
+    Blackout,
+    Custom(Vec<Vec<bool>>), // mask[row][col], must be the same size as the board
+}
+
+impl FromStr for WinPattern {
+    type Err = ParseErr;
+
+    // custom masks are rows of 0/1 separated by slashes, eg. custom:101/010/101
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim() {
+            "rows" => WinPattern::Rows,
+            "columns" => WinPattern::Columns,
+            "diagonals" => WinPattern::Diagonals,
+            "corners" => WinPattern::FourCorners,
+            "blackout" => WinPattern::Blackout,
+            s => match s.strip_prefix("custom:") {
+                Some(mask) => WinPattern::Custom(
+                    mask.split('/')
+                        .map(|row| {
+                            row.chars()
+                                .map(|c| match c {
+                                    '0' => Ok(false),
+                                    '1' => Ok(true),
+                                    _ => Err(ParseErr(format!("invalid mask char {:?}", c))),
+                                })
+                                .collect()
+                        })
+                        .collect::<Result<_, _>>()?,
+                ),
+                None => return Err(ParseErr(format!("unknown win pattern {:?}", s))),
+            },
+        })
+    }
+}
+
+impl fmt::Display for WinPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WinPattern::Rows => write!(f, "row"),
+            WinPattern::Columns => write!(f, "column"),
+            WinPattern::Diagonals => write!(f, "diagonal"),
+            WinPattern::FourCorners => write!(f, "four corners"),
+            WinPattern::Blackout => write!(f, "blackout"),
+            WinPattern::Custom(_) => write!(f, "custom mask"),
+        }
+    }
+}
+
+// one set of cells that wins a board when all of them are marked
+#[derive(Clone, Debug)]
+struct WinLine {
+    pattern: usize, // index into the list of patterns being played
+    cells: Vec<usize>,
+}
+
+impl Board {
+    // big boards of big numbers can add up past a u32
+    fn unmarked_sum(&self) -> u64 {
+        self.numbers
+            .iter()
+            .filter(|n| !n.marked)
+            .map(|n| n.value as u64)
+            .sum()
+    }
+
+    // diagonals only exist on square boards, and masks that don't fit the board never win
+    fn win_lines(&self, patterns: &[WinPattern]) -> Vec<WinLine> {
+        let (rows, cols) = (self.rows, self.cols);
+        let mut lines = Vec::new();
+        for (pattern, win_pattern) in patterns.iter().enumerate() {
+            let mut cell_lists = match win_pattern {
+                WinPattern::Rows => (0..rows)
+                    .map(|r| (0..cols).map(|c| r * cols + c).collect())
+                    .collect(),
+                WinPattern::Columns => (0..cols)
+                    .map(|c| (0..rows).map(|r| r * cols + c).collect())
+                    .collect(),
+                WinPattern::Diagonals if rows == cols => vec![
+                    (0..rows).map(|i| i * cols + i).collect(),
+                    (0..rows).map(|i| i * cols + cols - 1 - i).collect(),
+                ],
+                WinPattern::Diagonals => Vec::new(),
+                WinPattern::FourCorners => {
+                    let mut corners = vec![0, cols - 1, (rows - 1) * cols, rows * cols - 1];
+                    corners.sort_unstable();
+                    corners.dedup();
+                    vec![corners]
+                }
+                WinPattern::Blackout => vec![(0..rows * cols).collect()],
+                WinPattern::Custom(mask)
+                    if mask.len() == rows && mask.iter().all(|r| r.len() == cols) =>
+                {
+                    vec![mask
+                        .iter()
+                        .flatten()
+                        .enumerate()
+                        .filter(|&(_, &set)| set)
+                        .map(|(cell, _)| cell)
+                        .collect()]
+                }
+                WinPattern::Custom(_) => Vec::new(),
+            };
+            cell_lists.retain(|cells: &Vec<usize>| !cells.is_empty());
+            lines.extend(
+                cell_lists
+                    .into_iter()
+                    .map(|cells| WinLine { pattern, cells }),
+            );
+        }
+        lines
+    }
+}
 
 // this should probably be more closely related to Board, but idc, this is AoC
 #[derive(Debug)]
 struct BoardIndex {
     board_index: usize,
-    row_index: usize,
-    col_index: usize,
+    cell_index: usize,
 }
 
 impl BoardIndex {
     fn get_ref_mut<'a>(&self, boards: &'a mut [Board]) -> &'a mut Number {
-        &mut boards[self.board_index][self.row_index][self.col_index]
+        &mut boards[self.board_index].numbers[self.cell_index]
     }
 
-    fn won(&self, boards: &[Board]) -> bool {
-        boards[self.board_index][self.row_index]
-            .iter()
-            .all(|n| n.marked)
-            || boards[self.board_index]
-                .iter()
-                .all(|r| r[self.col_index].marked)
+    // returns the first line through this cell that's now fully marked
+    fn won<'a>(&self, boards: &[Board], lines: &'a [Vec<WinLine>]) -> Option<&'a WinLine> {
+        let board = &boards[self.board_index];
+        lines[self.board_index].iter().find(|line| {
+            line.cells.contains(&self.cell_index)
+                && line.cells.iter().all(|&cell| board.numbers[cell].marked)
+        })
     }
 }
 
-fn read_file(filename: impl AsRef<Path>) -> (Vec<u8>, Vec<Board>, HashMap<u8, Vec<BoardIndex>>) {
+fn read_file(filename: impl AsRef<Path>) -> (Vec<u32>, Vec<Board>, HashMap<u32, Vec<BoardIndex>>) {
     let file = File::open(filename).expect("File not found");
     let buf = BufReader::new(file);
-    let mut iter = buf.lines();
+    let mut iter = buf.lines().map(|line| line.expect("Failed to read line"));
 
     let draws = iter
         .next()
         .unwrap()
-        .unwrap()
         .split(',')
-        .map(|n| n.parse::<u8>().unwrap())
+        .map(|n| n.parse().unwrap())
         .collect();
 
+    // boards are separated by blank lines, and can be any size
     let mut boards = Vec::new();
     let mut map = HashMap::new();
-    while let Some(_) = iter.next() {
-        let mut board = [[Number {
-            value: 0,
-            marked: false,
-        }; 5]; 5];
-        for (row_index, row) in board.iter_mut().enumerate() {
-            for (col_index, value) in iter
-                .next()
-                .unwrap()
-                .unwrap()
-                .split_whitespace()
-                .map(|n| n.parse().unwrap())
-                .enumerate()
-            {
-                row[col_index].value = value;
+    let mut iter = iter.peekable();
+    while iter.peek().is_some() {
+        let mut board = Board {
+            rows: 0,
+            cols: 0,
+            numbers: Vec::new(),
+        };
+        for row in iter.by_ref().skip_while(|l| l.trim().is_empty()) {
+            if row.trim().is_empty() {
+                break;
+            }
+            let len_before = board.numbers.len();
+            for value in row.split_whitespace().map(|n| n.parse().unwrap()) {
                 map.entry(value).or_insert_with(Vec::new).push(BoardIndex {
                     board_index: boards.len(),
-                    row_index,
-                    col_index,
+                    cell_index: board.numbers.len(),
                 });
+                board.numbers.push(Number {
+                    value,
+                    marked: false,
+                });
+            }
+            let width = board.numbers.len() - len_before;
+            if board.rows == 0 {
+                board.cols = width;
             }
+            assert_eq!(
+                width, board.cols,
+                "All rows of a board must be the same width"
+            );
+            board.rows += 1;
+        }
+        if board.rows > 0 {
+            boards.push(board);
         }
-        boards.push(board);
     }
 
     (draws, boards, map)
 }
 
-fn main() {
-    let (draws, boards, map) = read_file("data/input.txt");
+#[derive(Clone, Debug)]
+struct Win {
+    board_index: usize,
+    draw: u32,
+    draw_index: usize,
+    line: WinLine,
+    unmarked_sum: u64,
+    score: u128, // a u64 times a u32 always fits
+}
 
-    // part 1
-    let mut boards_p1 = boards.clone();
-    let mut winning_board_op = None;
-    let mut winning_draw_op = None;
-    'outer: for draw in &draws {
-        if let Some(board_indexes) = map.get(draw) {
+#[derive(Debug)]
+struct Timeline {
+    wins: Vec<Win>,        // in the order the boards won
+    never_won: Vec<usize>, // indexes of boards that were still going when the draws ran out
+}
+
+impl Timeline {
+    // 0 is the first winner
+    fn kth_winner(&self, k: usize) -> Option<&Win> {
+        self.wins.get(k)
+    }
+}
+
+// plays every draw until all boards have won, keeping track of when each one won
+fn play(
+    draws: &[u32],
+    boards: &[Board],
+    map: &HashMap<u32, Vec<BoardIndex>>,
+    lines: &[Vec<WinLine>],
+) -> Timeline {
+    let mut boards = boards.to_vec();
+    let mut won_boards = vec![false; boards.len()];
+    let mut wins = Vec::new();
+    for (draw_index, &draw) in draws.iter().enumerate() {
+        if wins.len() == boards.len() {
+            break;
+        }
+        if let Some(board_indexes) = map.get(&draw) {
             for board_index in board_indexes {
-                board_index.get_ref_mut(&mut boards_p1).marked = true;
-                if board_index.won(&boards_p1) {
-                    winning_board_op = Some(boards_p1[board_index.board_index]);
-                    winning_draw_op = Some(*draw);
-                    break 'outer;
+                if !won_boards[board_index.board_index] {
+                    board_index.get_ref_mut(&mut boards).marked = true;
+                    if let Some(line) = board_index.won(&boards, lines) {
+                        won_boards[board_index.board_index] = true;
+                        let unmarked_sum = boards[board_index.board_index].unmarked_sum();
+                        wins.push(Win {
+                            board_index: board_index.board_index,
+                            draw,
+                            draw_index,
+                            line: line.clone(),
+                            unmarked_sum,
+                            score: unmarked_sum as u128 * draw as u128,
+                        });
+                    }
                 }
             }
         }
     }
-    if let Some(winning_board) = winning_board_op {
-        let unmarked_sum = winning_board
+    let never_won = (0..boards.len()).filter(|&i| !won_boards[i]).collect();
+    Timeline { wins, never_won }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placement {
+    First,
+    Last,
+}
+
+// the values of every line that can actually be completed with the numbers in draws
+fn feasible_lines(board: &Board, lines: &[WinLine], drawn: &HashSet<u32>) -> Vec<HashSet<u32>> {
+    lines
+        .iter()
+        .map(|line| {
+            line.cells
+                .iter()
+                .map(|&cell| board.numbers[cell].value)
+                .collect::<HashSet<_>>()
+        })
+        .filter(|values| values.is_subset(drawn))
+        .collect()
+}
+
+// the turn each board would win on if the draws were ordered just for it, or None if it can't win
+fn earliest_win_turns(
+    draws: &[u32],
+    boards: &[Board],
+    lines: &[Vec<WinLine>],
+) -> Vec<Option<usize>> {
+    let drawn: HashSet<u32> = draws.iter().copied().collect();
+    boards
+        .iter()
+        .zip(lines)
+        .map(|(board, lines)| {
+            feasible_lines(board, lines, &drawn)
+                .iter()
+                .map(|values| values.len())
+                .min()
+        })
+        .collect()
+}
+
+// picks a value to hold back from every line of the target board, such that every other board
+// still has a line without any held back values
+fn withhold(
+    target_lines: &[HashSet<u32>],
+    other_lines: &[Vec<HashSet<u32>>],
+    withheld: &mut HashSet<u32>,
+) -> bool {
+    let line = match target_lines.iter().find(|l| l.is_disjoint(withheld)) {
+        Some(line) => line,
+        None => return true,
+    };
+    for &value in line {
+        withheld.insert(value);
+        if other_lines
             .iter()
-            .map(|r| {
-                r.iter()
-                    .filter_map(|n| match n.marked {
-                        true => None,
-                        false => Some(n.value as u32),
-                    })
-                    .sum::<u32>()
-            })
-            .sum::<u32>();
-        let winning_draw = winning_draw_op.unwrap();
-        println!(
-            "Part 1: unmarked_sum = {}, winning_draw = {}, product = {}",
-            unmarked_sum,
-            winning_draw,
-            unmarked_sum * winning_draw as u32
-        );
+            .all(|lines| lines.iter().any(|l| l.is_disjoint(withheld)))
+            && withhold(target_lines, other_lines, withheld)
+        {
+            return true;
+        }
+        withheld.remove(&value);
+    }
+    false
+}
+
+// reorders draws so that the target board strictly wins first or last, or None if it can't
+// (or doesn't exist)
+fn order_draws(
+    draws: &[u32],
+    boards: &[Board],
+    lines: &[Vec<WinLine>],
+    target: usize,
+    placement: Placement,
+) -> Option<Vec<u32>> {
+    if target >= boards.len() {
+        return None;
+    }
+    let drawn: HashSet<u32> = draws.iter().copied().collect();
+    let mut all_lines: Vec<_> = boards
+        .iter()
+        .zip(lines)
+        .map(|(board, lines)| feasible_lines(board, lines, &drawn))
+        .collect();
+    let mut target_lines = all_lines.remove(target);
+    let other_lines = all_lines;
+
+    // the numbers to draw before everything else
+    let first: HashSet<u32> = match placement {
+        // draw the shortest line that doesn't complete a line on any other board
+        Placement::First => {
+            target_lines.sort_by_key(|values| values.len());
+            target_lines
+                .into_iter()
+                .find(|values| other_lines.iter().flatten().all(|l| !l.is_subset(values)))?
+        }
+        // let every other board win without drawing all of any line on the target board
+        Placement::Last => {
+            if target_lines.is_empty() || other_lines.iter().any(|lines| lines.is_empty()) {
+                return None;
+            }
+            let mut withheld = HashSet::new();
+            if !withhold(&target_lines, &other_lines, &mut withheld) {
+                return None;
+            }
+            other_lines
+                .iter()
+                .flat_map(|lines| lines.iter().find(|l| l.is_disjoint(&withheld)).unwrap())
+                .copied()
+                .collect()
+        }
+    };
+
+    let (mut order, rest): (Vec<_>, Vec<_>) = draws.iter().partition(|n| first.contains(n));
+    order.extend(rest);
+    Some(order)
+}
+
+fn print_win(name: &str, win: &Win, patterns: &[WinPattern]) {
+    println!(
+        "{}: unmarked_sum = {}, winning_draw = {}, product = {} (board {} won by {} on draw {}, cells {:?})",
+        name,
+        win.unmarked_sum,
+        win.draw,
+        win.score,
+        win.board_index,
+        patterns[win.line.pattern],
+        win.draw_index + 1,
+        win.line.cells
+    );
+}
+
+fn main() {
+    let (draws, boards, map) = read_file("data/input.txt");
+
+    // win patterns can be passed as a comma separated list, eg. rows,columns,diagonals
+    let patterns: Vec<WinPattern> = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "rows,columns".to_string())
+        .split(',')
+        .map(|s| s.parse().unwrap_or_else(|e: ParseErr| panic!("{}", e.0)))
+        .collect();
+    let lines: Vec<_> = boards.iter().map(|b| b.win_lines(&patterns)).collect();
+
+    let timeline = play(&draws, &boards, &map, &lines);
+
+    // part 1
+    if let Some(win) = timeline.kth_winner(0) {
+        print_win("Part 1", win, &patterns);
     }
 
     // part 2
-    // just use boards here since we don't need it again after this
-    let mut boards = boards;
-    winning_board_op = None;
-    winning_draw_op = None;
-    let mut won_boards = HashMap::new();
-    for draw in &draws {
-        if let Some(board_indexes) = map.get(draw) {
-            for board_index in board_indexes {
-                if won_boards.get(&board_index.board_index).is_none() {
-                    board_index.get_ref_mut(&mut boards).marked = true;
-                    if board_index.won(&boards) {
-                        winning_board_op = Some(boards[board_index.board_index]);
-                        winning_draw_op = Some(*draw);
-                        won_boards.insert(board_index.board_index, true);
-                    }
-                }
+    if let Some(win) = timeline.wins.last() {
+        print_win("Part 2", win, &patterns);
+    }
+
+    println!(
+        "{} of {} boards won, never won: {:?}",
+        timeline.wins.len(),
+        boards.len(),
+        timeline.never_won
+    );
+
+    // draw order analysis for one board, which can be passed after the patterns
+    let target: usize = env::args()
+        .nth(2)
+        .map_or(0, |s| s.parse().expect("Invalid board index"));
+    if target >= boards.len() {
+        println!("There's no board {}, only {} boards", target, boards.len());
+        return;
+    }
+    println!(
+        "Earliest possible winning turn for each board: {:?}",
+        earliest_win_turns(&draws, &boards, &lines)
+    );
+    for (name, placement) in [("first", Placement::First), ("last", Placement::Last)] {
+        match order_draws(&draws, &boards, &lines, target, placement) {
+            Some(order) => {
+                let timeline = play(&order, &boards, &map, &lines);
+                println!(
+                    "Board {} wins {} (as winner {}) with draws {:?}",
+                    target,
+                    name,
+                    timeline
+                        .wins
+                        .iter()
+                        .position(|w| w.board_index == target)
+                        .unwrap()
+                        + 1,
+                    order
+                );
             }
+            None => println!("Board {} can't win {}", target, name),
         }
     }
-    if let Some(winning_board) = winning_board_op {
-        let unmarked_sum = winning_board
-            .iter()
-            .map(|r| {
-                r.iter()
-                    .filter_map(|n| match n.marked {
-                        true => None,
-                        false => Some(n.value as u32),
-                    })
-                    .sum::<u32>()
-            })
-            .sum::<u32>();
-        let winning_draw = winning_draw_op.unwrap();
-        println!(
-            "Part 2: unmarked_sum = {}, winning_draw = {}, product = {}",
-            unmarked_sum,
-            winning_draw,
-            unmarked_sum * winning_draw as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Example = (
+        Vec<u32>,
+        Vec<Board>,
+        HashMap<u32, Vec<BoardIndex>>,
+        Vec<Vec<WinLine>>,
+    );
+
+    fn example() -> Example {
+        let (draws, boards, map) = read_file("data/example.txt");
+        let patterns = [WinPattern::Rows, WinPattern::Columns];
+        let lines = boards.iter().map(|b| b.win_lines(&patterns)).collect();
+        (draws, boards, map, lines)
+    }
+
+    #[test]
+    fn example_scores() {
+        let (draws, boards, map, lines) = example();
+        let timeline = play(&draws, &boards, &map, &lines);
+        assert_eq!(timeline.kth_winner(0).unwrap().score, 4512);
+        assert_eq!(timeline.wins.last().unwrap().score, 1924);
+        assert!(timeline.never_won.is_empty());
+    }
+
+    #[test]
+    fn order_draws_first_and_last() {
+        let (draws, boards, map, lines) = example();
+        for target in 0..boards.len() {
+            let order = order_draws(&draws, &boards, &lines, target, Placement::First).unwrap();
+            let timeline = play(&order, &boards, &map, &lines);
+            assert_eq!(timeline.kth_winner(0).unwrap().board_index, target);
+            assert_eq!(timeline.wins[0].draw_index, 4);
+
+            let order = order_draws(&draws, &boards, &lines, target, Placement::Last).unwrap();
+            let timeline = play(&order, &boards, &map, &lines);
+            assert_eq!(timeline.wins.last().unwrap().board_index, target);
+            assert_eq!(timeline.wins.len(), boards.len());
+        }
+    }
+
+    #[test]
+    fn order_draws_impossible() {
+        let (_, boards, _, lines) = example();
+        // only the top row of the first board can be completed
+        let draws = [22, 13, 17, 11, 0];
+        assert_eq!(
+            earliest_win_turns(&draws, &boards, &lines),
+            [Some(5), None, None]
         );
+        assert!(order_draws(&draws, &boards, &lines, 0, Placement::First).is_some());
+        assert_eq!(
+            order_draws(&draws, &boards, &lines, 0, Placement::Last),
+            None
+        );
+        assert_eq!(
+            order_draws(&draws, &boards, &lines, 1, Placement::First),
+            None
+        );
+    }
+
+    #[test]
+    fn order_draws_bad_target() {
+        let (draws, boards, _, lines) = example();
+        assert_eq!(
+            order_draws(&draws, &boards, &lines, boards.len(), Placement::Last),
+            None
+        );
+        assert_eq!(order_draws(&draws, &[], &[], 0, Placement::First), None);
+    }
+
+    #[test]
+    fn big_scores() {
+        let value = u32::MAX;
+        let board = Board {
+            rows: 2,
+            cols: 2,
+            numbers: vec![
+                Number {
+                    value,
+                    marked: false
+                };
+                4
+            ],
+        };
+        let mut map = HashMap::new();
+        map.insert(
+            value,
+            (0..4)
+                .map(|cell_index| BoardIndex {
+                    board_index: 0,
+                    cell_index,
+                })
+                .collect(),
+        );
+        let boards = [board];
+        let lines: Vec<_> = boards
+            .iter()
+            .map(|b| b.win_lines(&[WinPattern::Rows]))
+            .collect();
+        assert_eq!(boards[0].unmarked_sum(), 4 * value as u64);
+        // the top row wins as soon as both its cells are marked, which would overflow a u32 score
+        let timeline = play(&[value], &boards, &map, &lines);
+        assert_eq!(timeline.wins[0].unmarked_sum, 2 * value as u64);
+        assert_eq!(timeline.wins[0].score, 2 * value as u128 * value as u128);
     }
 }
@@ -1,7 +1,9 @@
 use std::{
-    cmp::{max, min, Ordering},
+    cmp::{max, min},
+    collections::{HashMap, HashSet},
+    env,
     fs::File,
-    io::{prelude::*, BufReader},
+    io::{self, prelude::*, BufReader, BufWriter},
     num::ParseIntError,
     path::Path,
     str::FromStr,
@@ -15,10 +17,10 @@ impl From<ParseIntError> for ParseErr {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 struct Point {
-    x: usize,
-    y: usize,
+    x: i64,
+    y: i64,
 }
 
 impl FromStr for Point {
@@ -61,54 +63,552 @@ fn read_file(filename: impl AsRef<Path>) -> Vec<Line> {
         .collect()
 }
 
-fn birange(a: usize, b: usize) -> Vec<usize> {
-    if a < b {
-        (a..=b).collect()
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
     } else {
-        (b..=a).rev().collect()
+        gcd(b, a % b)
     }
 }
 
-fn get_overlaps(input: &[Line]) -> usize {
-    let points: Vec<Point> = input.iter().map(|l| [l.a, l.b]).flatten().collect();
+fn cross(a: (i128, i128), b: (i128, i128)) -> i128 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+impl Line {
+    // smallest lattice step from a to b, and how many steps it takes
+    // a is always <= b, so the step never goes left
+    fn step(&self) -> ((i64, i64), i64) {
+        let (dx, dy) = (self.b.x - self.a.x, self.b.y - self.a.y);
+        match gcd(dx, dy) {
+            0 => ((1, 0), 0), // a single point, treat it as a horizontal line
+            g => ((dx / g, dy / g), g),
+        }
+    }
+
+    // identifies the infinite line this segment is on, so collinear segments get the same key
+    fn key(&self) -> (i64, i64, i128) {
+        let ((dx, dy), _) = self.step();
+        (
+            dx,
+            dy,
+            cross(
+                (dx as i128, dy as i128),
+                (self.a.x as i128, self.a.y as i128),
+            ),
+        )
+    }
+
+    // position along the line, in units of dot(step, step)
+    fn position(&self, p: Point) -> i128 {
+        let ((dx, dy), _) = self.step();
+        dx as i128 * p.x as i128 + dy as i128 * p.y as i128
+    }
+
+    // the one point where two non-parallel segments cross, if it's a lattice point on both of them
+    fn crossing(&self, other: &Line) -> Option<Point> {
+        let ((dx1, dy1), len1) = self.step();
+        let ((dx2, dy2), len2) = other.step();
+        let d1 = (dx1 as i128, dy1 as i128);
+        let d2 = (dx2 as i128, dy2 as i128);
+        let denom = cross(d1, d2);
+        if denom == 0 {
+            return None;
+        }
+        let diff = (
+            other.a.x as i128 - self.a.x as i128,
+            other.a.y as i128 - self.a.y as i128,
+        );
+        let (t, u) = (cross(diff, d2), cross(diff, d1));
+        if t % denom != 0 || u % denom != 0 {
+            return None;
+        }
+        let (t, u) = (t / denom, u / denom);
+        if (0..=len1 as i128).contains(&t) && (0..=len2 as i128).contains(&u) {
+            Some(Point {
+                x: (self.a.x as i128 + t * d1.0) as i64,
+                y: (self.a.y as i128 + t * d1.1) as i64,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum LineKind {
+    Horizontal,
+    Vertical,
+    Diagonal, // exactly 45 degrees
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Raster {
+    Exact,     // only the lattice points that are exactly on the line
+    Bresenham, // one point per step along the major axis, like drawing it on a screen
+}
+
+impl Line {
+    fn kind(&self) -> LineKind {
+        let (dx, dy) = (self.b.x - self.a.x, self.b.y - self.a.y);
+        if dy == 0 {
+            LineKind::Horizontal
+        } else if dx == 0 {
+            LineKind::Vertical
+        } else if dx.abs() == dy.abs() {
+            LineKind::Diagonal
+        } else {
+            LineKind::Other
+        }
+    }
+
+    fn points(&self, raster: Raster) -> Vec<Point> {
+        match raster {
+            Raster::Exact => {
+                let ((dx, dy), len) = self.step();
+                (0..=len)
+                    .map(|t| Point {
+                        x: self.a.x + t * dx,
+                        y: self.a.y + t * dy,
+                    })
+                    .collect()
+            }
+            Raster::Bresenham => {
+                let (dx, dy) = ((self.b.x - self.a.x).abs(), -(self.b.y - self.a.y).abs());
+                let sy = if self.a.y < self.b.y { 1 } else { -1 };
+                let mut point = self.a;
+                let mut err = dx + dy;
+                let mut points = vec![point];
+                while point != self.b {
+                    let e2 = 2 * err;
+                    if e2 >= dy {
+                        err += dy;
+                        point.x += 1; // a is always left of b
+                    }
+                    if e2 <= dx {
+                        err += dx;
+                        point.y += sy;
+                    }
+                    points.push(point);
+                }
+                points
+            }
+        }
+    }
+}
+
+// the most points an OverlapMap or heatmap is allowed to have
+const MAX_POINTS: u128 = 100_000_000;
+
+// sparse map of how many lines cover each point
+struct OverlapMap {
+    counts: HashMap<Point, usize>,
+}
+
+impl OverlapMap {
+    fn new(input: &[Line], raster: Raster) -> Self {
+        let mut counts = HashMap::new();
+        for line in input {
+            for point in line.points(raster) {
+                *counts.entry(point).or_insert(0) += 1;
+            }
+        }
+        OverlapMap { counts }
+    }
+
+    fn count(&self, point: Point) -> usize {
+        *self.counts.get(&point).unwrap_or(&0)
+    }
+
+    // number of points covered by at least k lines
+    fn at_least(&self, k: usize) -> usize {
+        self.counts.values().filter(|&&n| n >= k).count()
+    }
 
-    let mut bottom_right = points[0];
-    for point in &points {
-        bottom_right.x = max(point.x, bottom_right.x);
-        bottom_right.y = max(point.y, bottom_right.y);
+    // the highest overlap and every point that has it, sorted
+    fn max(&self) -> (usize, Vec<Point>) {
+        let max_count = self.counts.values().copied().max().unwrap_or(0);
+        let mut points: Vec<_> = self
+            .counts
+            .iter()
+            .filter(|(_, &n)| n == max_count)
+            .map(|(&p, _)| p)
+            .collect();
+        points.sort_unstable();
+        (max_count, points)
     }
 
-    let mut grid = vec![ // grid[x][y]
-        vec![0; bottom_right.y + 1];
-        bottom_right.x + 1
-    ];
+    // greyscale image of the bounding box of all lines, where white is the max overlap
+    fn write_pgm(&self, filename: impl AsRef<Path>) -> io::Result<()> {
+        let (max_count, _) = self.max();
+        let mut out = BufWriter::new(File::create(filename)?);
+        if self.counts.is_empty() {
+            writeln!(out, "P5\n0 0\n255")?;
+            return out.flush();
+        }
+        let (min_x, max_x) = min_max(self.counts.keys().map(|p| p.x));
+        let (min_y, max_y) = min_max(self.counts.keys().map(|p| p.y));
+        let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+        if width as u128 * height as u128 > MAX_POINTS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "heatmap would be too big",
+            ));
+        }
+        writeln!(out, "P5\n{} {}\n255", width, height)?;
+        for y in min_y..=max_y {
+            let row: Vec<u8> = (min_x..=max_x)
+                .map(|x| (self.count(Point { x, y }) * 255 / max_count.max(1)) as u8)
+                .collect();
+            out.write_all(&row)?;
+        }
+        out.flush()
+    }
+}
+
+// how many points OverlapMap::new would store, counting overlaps more than once
+fn raster_size(input: &[Line], raster: Raster) -> u128 {
+    input
+        .iter()
+        .map(|line| {
+            let steps = match raster {
+                Raster::Exact => line.step().1,
+                Raster::Bresenham => (line.b.x - line.a.x).max((line.b.y - line.a.y).abs()),
+            };
+            steps as u128 + 1
+        })
+        .sum()
+}
+
+fn min_max(iter: impl Iterator<Item = i64>) -> (i64, i64) {
+    iter.fold((i64::MAX, i64::MIN), |(lo, hi), n| (lo.min(n), hi.max(n)))
+}
 
+// indexes of every line that the point is exactly on
+fn lines_through(input: &[Line], point: Point) -> Vec<usize> {
+    input
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| {
+            let ((dx, dy), _) = line.step();
+            let offset = ((point.x - line.a.x) as i128, (point.y - line.a.y) as i128);
+            cross(offset, (dx as i128, dy as i128)) == 0
+                && (line.position(line.a)..=line.position(line.b)).contains(&line.position(point))
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// never rasterizes the lines, so it only depends on the number of lines and not their length
+// collinear segments are grouped and swept to find the ranges covered at least twice,
+// then crossings between non-parallel segments are added on top
+fn get_overlaps(input: &[Line]) -> usize {
+    let mut groups: HashMap<_, Vec<&Line>> = HashMap::new();
     for line in input {
-        let mut all_x = birange(line.a.x, line.b.x);
-        let mut all_y = birange(line.a.y, line.b.y);
-        match all_x.len().cmp(&all_y.len()) {
-            Ordering::Less => all_x = vec![line.a.x; all_y.len()],
-            Ordering::Greater => all_y = vec![line.a.y; all_x.len()],
-            _ => (),
+        groups.entry(line.key()).or_default().push(line);
+    }
+
+    let mut regions = HashMap::new();
+    let mut total: usize = 0;
+    for (key, lines) in &groups {
+        let ((dx, dy), _) = lines[0].step();
+        let unit = dx as i128 * dx as i128 + dy as i128 * dy as i128;
+        // starts sort before ends at the same position, since the segments are closed
+        let mut events: Vec<(i128, i32)> = lines
+            .iter()
+            .flat_map(|l| [(l.position(l.a), -1), (l.position(l.b), 1)])
+            .collect();
+        events.sort_unstable();
+        let mut covered = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+        for (pos, event) in events {
+            depth -= event;
+            if event == -1 && depth == 2 {
+                start = pos;
+            } else if event == 1 && depth == 1 {
+                covered.push((start, pos));
+                total += ((pos - start) / unit + 1) as usize;
+            }
         }
-        for (x, y) in all_x.iter().zip(&all_y) {
-            grid[*x][*y] += 1;
+        if !covered.is_empty() {
+            regions.insert(*key, covered);
         }
     }
 
-    grid.iter().flatten().filter(|&n| *n >= 2).count()
+    // a crossing can land in the covered regions of any number of groups
+    // if it's in none it's a new overlap, and if it's in several it was counted more than once
+    let mut seen = HashMap::new();
+    for (i, l1) in input.iter().enumerate() {
+        for l2 in &input[i + 1..] {
+            if let Some(point) = l1.crossing(l2) {
+                let groups_at = seen.entry(point).or_insert_with(HashSet::new);
+                for key in [l1.key(), l2.key()] {
+                    if let Some(covered) = regions.get(&key) {
+                        let pos = key.0 as i128 * point.x as i128 + key.1 as i128 * point.y as i128;
+                        let idx = covered.partition_point(|&(_, end)| end < pos);
+                        if idx < covered.len() && covered[idx].0 <= pos {
+                            groups_at.insert(key);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    for groups_at in seen.values() {
+        match groups_at.len() {
+            0 => total += 1,
+            n => total -= n - 1,
+        }
+    }
+    total
 }
 
 fn main() {
     let input = read_file("data/input.txt");
 
+    let mut kinds = HashMap::new();
+    for line in &input {
+        *kinds.entry(line.kind()).or_insert(0) += 1;
+    }
+    println!(
+        "Lines: {} horizontal, {} vertical, {} diagonal, {} other",
+        kinds.get(&LineKind::Horizontal).unwrap_or(&0),
+        kinds.get(&LineKind::Vertical).unwrap_or(&0),
+        kinds.get(&LineKind::Diagonal).unwrap_or(&0),
+        kinds.get(&LineKind::Other).unwrap_or(&0)
+    );
+
     // part 1
     {
         let mut input = input.clone();
-        input.retain(|l| l.a.x == l.b.x || l.a.y == l.b.y); // only keep horizontal/vertical
+        input.retain(|l| matches!(l.kind(), LineKind::Horizontal | LineKind::Vertical));
         println!("Part 1: {} overlaps", get_overlaps(&input));
     }
 
     // part 2
     println!("Part 2: {} overlaps", get_overlaps(&input));
+
+    // the maps keep every point of every line, which coordinates in the billions won't fit in
+    if raster_size(&input, Raster::Bresenham) > MAX_POINTS {
+        println!("Too many points to map them one at a time");
+        return;
+    }
+    let overlaps = OverlapMap::new(&input, Raster::Exact);
+    let (max_count, max_points) = overlaps.max();
+    match max_points.first() {
+        Some(&point) => println!(
+            "Most overlaps: {} lines at {} points, eg. {:?} which is on lines {:?}",
+            max_count,
+            max_points.len(),
+            point,
+            lines_through(&input, point)
+        ),
+        None => println!("No lines"),
+    }
+    for k in 2..=max_count {
+        println!("At least {} overlaps: {}", k, overlaps.at_least(k));
+    }
+    println!(
+        "Bresenham rasterization: {} overlaps",
+        OverlapMap::new(&input, Raster::Bresenham).at_least(2)
+    );
+
+    // a heatmap is only written if a path is passed
+    if let Some(path) = env::args().nth(1) {
+        overlaps.write_pgm(&path).expect("Failed to write heatmap");
+        println!("Wrote heatmap to {}", path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn lines(input: &[&str]) -> Vec<Line> {
+        input.iter().filter_map(|s| s.parse().ok()).collect()
+    }
+
+    // rasterizing is slow but obviously right, so it's what the sweep is checked against
+    fn check(input: &[&str], expected: usize) {
+        let input = lines(input);
+        assert_eq!(OverlapMap::new(&input, Raster::Exact).at_least(2), expected);
+        assert_eq!(get_overlaps(&input), expected);
+    }
+
+    #[test]
+    fn example() {
+        let input = read_file("data/example.txt");
+        let straight: Vec<_> = input
+            .iter()
+            .copied()
+            .filter(|l| matches!(l.kind(), LineKind::Horizontal | LineKind::Vertical))
+            .collect();
+        assert_eq!(get_overlaps(&straight), 5);
+        assert_eq!(get_overlaps(&input), 12);
+    }
+
+    #[test]
+    fn collinear_overlaps() {
+        // partial overlap, and segments that only share an endpoint
+        check(&["0,0 -> 5,0", "3,0 -> 9,0", "9,0 -> 12,0"], 4);
+        // nested, and three deep
+        check(&["0,0 -> 9,9", "2,2 -> 4,4", "3,3 -> 7,7"], 6);
+        // steeper lines only cover every few lattice points
+        check(&["0,0 -> 6,3", "2,1 -> 8,4", "0,1 -> 6,4"], 3);
+        // collinear but not touching
+        check(&["0,0 -> 2,0", "4,0 -> 6,0"], 0);
+    }
+
+    #[test]
+    fn concurrent_crossings() {
+        // three lines through the same point is still one overlap
+        check(&["0,5 -> 10,5", "5,0 -> 5,10", "0,0 -> 10,10"], 1);
+        // a crossing inside a collinear overlap isn't counted again
+        check(&["0,0 -> 6,0", "2,0 -> 8,0", "4,-3 -> 4,3"], 5);
+        // and neither is one where two overlapping groups cross each other
+        check(
+            &["0,0 -> 6,0", "2,0 -> 8,0", "4,-3 -> 4,3", "4,-2 -> 4,5"],
+            10,
+        );
+        // lines that cross between lattice points
+        check(&["0,0 -> 1,1", "0,1 -> 1,0"], 0);
+        check(&["0,0 -> 3,1", "0,1 -> 3,0"], 0);
+    }
+
+    #[test]
+    fn single_points_and_far_lines() {
+        check(&["3,3 -> 3,3", "3,3 -> 3,3", "0,0 -> 6,6"], 1);
+        let input = lines(&[
+            "-1000000000,0 -> 1000000000,0",
+            "0,-1000000000 -> 0,1000000000",
+            "-5,0 -> 5,0",
+        ]);
+        assert_eq!(get_overlaps(&input), 11);
+    }
+
+    #[test]
+    fn kinds() {
+        let kinds: Vec<LineKind> = lines(&[
+            "0,3 -> 5,3",
+            "2,9 -> 2,1",
+            "0,0 -> 4,4",
+            "5,0 -> 0,5",
+            "0,0 -> 6,3",
+            "1,1 -> 1,1",
+        ])
+        .iter()
+        .map(Line::kind)
+        .collect();
+        assert_eq!(
+            kinds,
+            [
+                LineKind::Horizontal,
+                LineKind::Vertical,
+                LineKind::Diagonal,
+                LineKind::Diagonal,
+                LineKind::Other,
+                LineKind::Horizontal,
+            ]
+        );
+    }
+
+    // one point per step along the major axis, moving at most one along the other, end to end
+    fn check_bresenham(line: &str) -> Vec<Point> {
+        let line = lines(&[line])[0];
+        let points = line.points(Raster::Bresenham);
+        let major = (line.b.x - line.a.x).abs().max((line.b.y - line.a.y).abs());
+        assert_eq!(points.len() as i64, major + 1);
+        assert_eq!(points[0], line.a);
+        assert_eq!(points[points.len() - 1], line.b);
+        for pair in points.windows(2) {
+            let (dx, dy) = (pair[1].x - pair[0].x, pair[1].y - pair[0].y);
+            assert!(dx.abs() <= 1 && dy.abs() <= 1 && (dx, dy) != (0, 0));
+        }
+        points
+    }
+
+    #[test]
+    fn bresenham() {
+        let shallow = check_bresenham("0,0 -> 5,2");
+        assert!(shallow.windows(2).all(|p| p[1].x - p[0].x == 1));
+        let steep = check_bresenham("0,0 -> 2,5");
+        assert!(steep.windows(2).all(|p| p[1].y - p[0].y == 1));
+        let down = check_bresenham("0,5 -> 6,1");
+        assert!(down
+            .windows(2)
+            .all(|p| p[1].x - p[0].x == 1 && p[1].y <= p[0].y));
+        let steep_down = check_bresenham("3,0 -> 0,7");
+        assert!(steep_down.windows(2).all(|p| p[1].y - p[0].y == -1));
+        assert_eq!(check_bresenham("2,2 -> 2,2"), [Point { x: 2, y: 2 }]);
+
+        // lines exactly on the lattice come out the same either way
+        for line in lines(&["0,0 -> 4,4", "0,4 -> 4,0", "1,1 -> 1,6", "0,0 -> 9,0"]) {
+            assert_eq!(line.points(Raster::Bresenham), line.points(Raster::Exact));
+        }
+        // and otherwise exact only has the points that are exactly on the line
+        let line = lines(&["0,0 -> 6,3"])[0];
+        assert_eq!(
+            line.points(Raster::Exact),
+            [
+                Point { x: 0, y: 0 },
+                Point { x: 2, y: 1 },
+                Point { x: 4, y: 2 },
+                Point { x: 6, y: 3 }
+            ]
+        );
+    }
+
+    #[test]
+    fn overlap_map_queries() {
+        let input = lines(&["0,0 -> 4,0", "2,0 -> 2,4", "0,0 -> 4,4", "1,0 -> 3,0"]);
+        let overlaps = OverlapMap::new(&input, Raster::Exact);
+        assert_eq!(overlaps.count(Point { x: 2, y: 0 }), 3);
+        assert_eq!(overlaps.count(Point { x: 0, y: 0 }), 2);
+        assert_eq!(overlaps.count(Point { x: 2, y: 2 }), 2);
+        assert_eq!(overlaps.count(Point { x: 4, y: 3 }), 0);
+        assert_eq!(overlaps.at_least(1), 5 + 4 + 3);
+        assert_eq!(overlaps.at_least(2), 5);
+        assert_eq!(overlaps.at_least(3), 1);
+        assert_eq!(overlaps.at_least(4), 0);
+        assert_eq!(overlaps.max(), (3, vec![Point { x: 2, y: 0 }]));
+        assert_eq!(lines_through(&input, Point { x: 2, y: 0 }), [0, 1, 3]);
+        assert_eq!(lines_through(&input, Point { x: 2, y: 2 }), [1, 2]);
+        assert_eq!(lines_through(&input, Point { x: 5, y: 0 }), []);
+        assert_eq!(raster_size(&input, Raster::Exact), 5 + 5 + 5 + 3);
+    }
+
+    #[test]
+    fn overlap_map_empty() {
+        let overlaps = OverlapMap::new(&[], Raster::Exact);
+        assert_eq!(overlaps.max(), (0, Vec::new()));
+        assert_eq!(overlaps.at_least(2), 0);
+        let path = env::temp_dir().join("day_5_empty.pgm");
+        overlaps.write_pgm(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"P5\n0 0\n255\n");
+    }
+
+    #[test]
+    fn heatmap() {
+        let input = lines(&["0,0 -> 2,0", "1,0 -> 1,1"]);
+        let overlaps = OverlapMap::new(&input, Raster::Exact);
+        let path = env::temp_dir().join("day_5_heatmap.pgm");
+        overlaps.write_pgm(&path).unwrap();
+        let mut expected = b"P5\n3 2\n255\n".to_vec();
+        expected.extend([127, 255, 127, 0, 127, 0]);
+        assert_eq!(fs::read(&path).unwrap(), expected);
+
+        // far apart points make a sparse map but a huge image
+        let input = lines(&[
+            "0,0 -> 0,0",
+            "1000000000,1000000000 -> 1000000000,1000000000",
+        ]);
+        let overlaps = OverlapMap::new(&input, Raster::Exact);
+        assert!(overlaps
+            .write_pgm(env::temp_dir().join("day_5_huge.pgm"))
+            .is_err());
+        assert!(raster_size(&lines(&["0,0 -> 1000000000,0"]), Raster::Exact) > MAX_POINTS);
+    }
 }
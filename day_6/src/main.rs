@@ -1,6 +1,7 @@
 use std::{
+    env, fmt,
     fs::File,
-    io::{prelude::*, BufReader},
+    io::{self, prelude::*, BufReader, BufWriter},
     path::Path,
 };
 
@@ -16,47 +17,487 @@ fn read_file(filename: impl AsRef<Path>) -> Vec<u8> {
         .collect()
 }
 
-fn main() {
-    let input = read_file("data/input.txt");
+// just enough of an arbitrary precision unsigned int to count fish exactly
+// limbs are base 2^32, least significant first
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BigUint(Vec<u32>);
 
-    // part 1
-    {
-        let mut input = input.clone();
-        for _ in 1..=80 {
-            let mut num_create = 0;
-            input = input
-                .iter()
-                .map(|fish| match fish {
-                    0 => {
-                        num_create += 1;
-                        6
-                    }
-                    _ => fish - 1,
+impl BigUint {
+    fn from_u64(n: u64) -> Self {
+        let mut big = BigUint(vec![n as u32, (n >> 32) as u32]);
+        big.trim();
+        big
+    }
+
+    fn trim(&mut self) {
+        while self.0.last() == Some(&0) {
+            self.0.pop();
+        }
+    }
+
+    fn add(&self, other: &BigUint) -> BigUint {
+        let mut limbs = Vec::with_capacity(self.0.len().max(other.0.len()) + 1);
+        let mut carry = 0;
+        for i in 0..self.0.len().max(other.0.len()) {
+            let sum =
+                *self.0.get(i).unwrap_or(&0) as u64 + *other.0.get(i).unwrap_or(&0) as u64 + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+        let mut big = BigUint(limbs);
+        big.trim();
+        big
+    }
+
+    fn mul(&self, other: &BigUint) -> BigUint {
+        let mut limbs = vec![0_u32; self.0.len() + other.0.len()];
+        for (i, &a) in self.0.iter().enumerate() {
+            let mut carry = 0;
+            for (j, &b) in other.0.iter().enumerate() {
+                let cur = limbs[i + j] as u64 + a as u64 * b as u64 + carry;
+                limbs[i + j] = cur as u32;
+                carry = cur >> 32;
+            }
+            limbs[i + other.0.len()] = carry as u32;
+        }
+        let mut big = BigUint(limbs);
+        big.trim();
+        big
+    }
+
+    // loses precision of course, but good enough for ratios
+    fn to_f64(&self) -> f64 {
+        self.0
+            .iter()
+            .rev()
+            .fold(0.0, |acc, &limb| acc * 4294967296.0 + limb as f64)
+    }
+}
+
+impl fmt::Display for BigUint {
+    // repeatedly divides by 10^9 to get the decimal digits in chunks
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut limbs = self.0.clone();
+        let mut chunks = Vec::new();
+        while !limbs.is_empty() {
+            let mut rem = 0;
+            for limb in limbs.iter_mut().rev() {
+                let cur = (rem << 32) | *limb as u64;
+                *limb = (cur / 1_000_000_000) as u32;
+                rem = cur % 1_000_000_000;
+            }
+            chunks.push(rem);
+            while limbs.last() == Some(&0) {
+                limbs.pop();
+            }
+        }
+        match chunks.pop() {
+            Some(first) => write!(f, "{}", first)?,
+            None => return write!(f, "0"),
+        }
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+// what the fish counts are stored as, so the same solver can count exactly or modulo a prime
+trait Arith {
+    type Num: Clone + fmt::Display;
+
+    fn num(&self, n: u64) -> Self::Num;
+    fn add(&self, a: &Self::Num, b: &Self::Num) -> Self::Num;
+    fn mul(&self, a: &Self::Num, b: &Self::Num) -> Self::Num;
+}
+
+struct Exact;
+
+impl Arith for Exact {
+    type Num = BigUint;
+
+    fn num(&self, n: u64) -> BigUint {
+        BigUint::from_u64(n)
+    }
+
+    fn add(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a.add(b)
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a.mul(b)
+    }
+}
+
+struct Modulo(u64);
+
+impl Arith for Modulo {
+    type Num = u64;
+
+    fn num(&self, n: u64) -> u64 {
+        n % self.0
+    }
+
+    fn add(&self, a: &u64, b: &u64) -> u64 {
+        ((*a as u128 + *b as u128) % self.0 as u128) as u64
+    }
+
+    fn mul(&self, a: &u64, b: &u64) -> u64 {
+        ((*a as u128 * *b as u128) % self.0 as u128) as u64
+    }
+}
+
+struct Float;
+
+impl Arith for Float {
+    type Num = f64;
+
+    fn num(&self, n: u64) -> f64 {
+        n as f64
+    }
+
+    fn add(&self, a: &f64, b: &f64) -> f64 {
+        a + b
+    }
+
+    fn mul(&self, a: &f64, b: &f64) -> f64 {
+        a * b
+    }
+}
+
+type Matrix<T> = Vec<Vec<T>>;
+
+fn mat_mul<A: Arith>(arith: &A, a: &Matrix<A::Num>, b: &Matrix<A::Num>) -> Matrix<A::Num> {
+    let n = a.len();
+    (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| {
+                    (0..n).fold(arith.num(0), |acc, k| {
+                        arith.add(&acc, &arith.mul(&a[i][k], &b[k][j]))
+                    })
                 })
+                .collect()
+        })
+        .collect()
+}
+
+/*
+one bucket for each timer value [0-newborn]
+each day:
+    - move counts in buckets [1-newborn] left
+    - move count in bucket 0 to bucket newborn and also add to bucket reset
+that's linear, so n days is the transition matrix to the power of n
+*/
+#[derive(Debug, Clone, Copy)]
+struct Lifecycle {
+    reset: usize,   // timer value after a fish spawns, 6 normally
+    newborn: usize, // timer value of a new fish, 8 normally
+}
+
+impl Lifecycle {
+    fn new(reset: usize, newborn: usize) -> Self {
+        assert!(
+            reset <= newborn,
+            "Reset timer {} can't be past the newborn timer {}",
+            reset,
+            newborn
+        );
+        Lifecycle { reset, newborn }
+    }
+
+    // every fish needs a bucket to start in
+    fn check_input(&self, input: &[u8]) {
+        if let Some(&fish) = input.iter().find(|&&fish| fish as usize > self.newborn) {
+            panic!("Timer {} is past the newborn timer {}", fish, self.newborn);
+        }
+    }
+
+    fn transition<A: Arith>(&self, arith: &A) -> Matrix<A::Num> {
+        let n = self.newborn + 1;
+        let mut matrix = vec![vec![arith.num(0); n]; n];
+        for i in 0..n - 1 {
+            matrix[i][i + 1] = arith.num(1);
+        }
+        matrix[n - 1][0] = arith.num(1);
+        matrix[self.reset][0] = arith.add(&matrix[self.reset][0], &arith.num(1));
+        matrix
+    }
+
+    // O(log days) matrix multiplications
+    fn population<A: Arith>(&self, arith: &A, input: &[u8], days: u64) -> A::Num {
+        self.check_input(input);
+        let n = self.newborn + 1;
+        let mut result: Matrix<A::Num> = (0..n)
+            .map(|i| (0..n).map(|j| arith.num((i == j) as u64)).collect())
+            .collect();
+        let mut power = self.transition(arith);
+        let mut days = days;
+        while days > 0 {
+            if days & 1 == 1 {
+                result = mat_mul(arith, &result, &power);
+            }
+            power = mat_mul(arith, &power, &power);
+            days >>= 1;
+        }
+
+        // every fish ends up as the sum of the column for its starting timer
+        let mut total = arith.num(0);
+        for &fish in input {
+            for row in &result {
+                total = arith.add(&total, &row[fish as usize]);
+            }
+        }
+        total
+    }
+
+    // bucket counts for every day from 0 to days, stepping one day at a time
+    fn history<A: Arith>(&self, arith: &A, input: &[u8], days: u64) -> Vec<Vec<A::Num>> {
+        self.check_input(input);
+        let mut buckets = vec![arith.num(0); self.newborn + 1];
+        for &fish in input {
+            buckets[fish as usize] = arith.add(&buckets[fish as usize], &arith.num(1));
+        }
+        let mut history = vec![buckets.clone()];
+        for _ in 1..=days {
+            // the zeros rotate round to become the newborns, and also join the reset bucket
+            buckets.rotate_left(1);
+            let zero_count = buckets[self.newborn].clone();
+            buckets[self.reset] = arith.add(&buckets[self.reset], &zero_count);
+            history.push(buckets.clone());
+        }
+        history
+    }
+
+    // the dominant eigenvalue of the transition matrix, found by power iteration
+    // this is what the ratio between consecutive days converges to
+    fn growth_rate(&self) -> f64 {
+        let matrix = self.transition(&Float);
+        let mut vector = vec![1.0; matrix.len()];
+        let mut rate = 0.0;
+        for _ in 0..10_000 {
+            let next: Vec<f64> = matrix
+                .iter()
+                .map(|row| row.iter().zip(&vector).map(|(a, b)| a * b).sum())
                 .collect();
-            input.append(&mut vec![8_u8; num_create]);
+            let new_rate = next.iter().sum::<f64>() / vector.iter().sum::<f64>();
+            vector = next.iter().map(|n| n / new_rate).collect();
+            if (new_rate - rate).abs() < 1e-15 {
+                return new_rate;
+            }
+            rate = new_rate;
         }
-        println!("Part 1: {} fish after 80 days", input.len());
+        rate
+    }
+}
+
+fn write_csv(filename: impl AsRef<Path>, history: &[Vec<BigUint>]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(filename)?);
+    write!(out, "day,total,growth")?;
+    for i in 0..history[0].len() {
+        write!(out, ",timer_{}", i)?;
     }
+    writeln!(out)?;
+    let mut prev_total: Option<BigUint> = None;
+    for (day, buckets) in history.iter().enumerate() {
+        let total = buckets
+            .iter()
+            .fold(BigUint::from_u64(0), |acc, n| acc.add(n));
+        write!(out, "{},{},", day, total)?;
+        if let Some(prev_total) = prev_total {
+            write!(out, "{}", total.to_f64() / prev_total.to_f64())?;
+        }
+        for bucket in buckets {
+            write!(out, ",{}", bucket)?;
+        }
+        writeln!(out)?;
+        prev_total = Some(total);
+    }
+    out.flush()
+}
+
+fn main() {
+    let input = read_file("data/input.txt");
+    let lifecycle = Lifecycle::new(6, 8);
+
+    // part 1
+    println!(
+        "Part 1: {} fish after 80 days",
+        lifecycle.population(&Exact, &input, 80)
+    );
 
     // part 2
-    /*
-    9 buckets [0-8]
-    each day:
-        - move counts in buckets [1-8] left
-        - move count in bucket 0 to bucket 8 and also add to bucket 6
-    */
-    let mut buckets = [0_u64; 9];
-    for fish in &input {
-        buckets[*fish as usize] += 1;
-    }
-    for _ in 1..=256 {
-        let zero_count = buckets[0];
-        for index in 1..9 {
-            buckets[index - 1] = buckets[index];
-        }
-        buckets[8] = zero_count;
-        buckets[6] += zero_count;
-    }
-    println!("Part 2: {} fish after 80 days", buckets.iter().sum::<u64>());
+    println!(
+        "Part 2: {} fish after 256 days",
+        lifecycle.population(&Exact, &input, 256)
+    );
+
+    let days = 1_000_000_000_000;
+    let modulus = 1_000_000_007;
+    println!(
+        "{} fish (mod {}) after {} days",
+        lifecycle.population(&Modulo(modulus), &input, days),
+        modulus,
+        days
+    );
+
+    let history = lifecycle.history(&Exact, &input, 256);
+    let totals: Vec<f64> = history
+        .iter()
+        .map(|buckets| buckets.iter().map(BigUint::to_f64).sum())
+        .collect();
+    println!(
+        "Growth ratio on day 256: {:.6}, asymptotic growth rate: {:.6}",
+        totals[256] / totals[255],
+        lifecycle.growth_rate()
+    );
+
+    // the full history is only written if a path is passed
+    if let Some(path) = env::args().nth(1) {
+        write_csv(&path, &history).expect("Failed to write csv");
+        println!("Wrote history to {}", path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn big(n: u128) -> BigUint {
+        BigUint::from_u64((n >> 64) as u64)
+            .mul(&BigUint::from_u64(1 << 32))
+            .mul(&BigUint::from_u64(1 << 32))
+            .add(&BigUint::from_u64(n as u64))
+    }
+
+    // decimal digits mod m, so big results can be compared with modular ones
+    fn reduce(n: &BigUint, m: u64) -> u64 {
+        n.to_string()
+            .bytes()
+            .fold(0, |acc, d| (acc * 10 + (d - b'0') as u64) % m)
+    }
+
+    #[test]
+    fn example() {
+        let input = read_file("data/example.txt");
+        let lifecycle = Lifecycle::new(6, 8);
+        assert_eq!(lifecycle.population(&Exact, &input, 18).to_string(), "26");
+        assert_eq!(lifecycle.population(&Exact, &input, 80).to_string(), "5934");
+        assert_eq!(
+            lifecycle.population(&Exact, &input, 256).to_string(),
+            "26984457539"
+        );
+    }
+
+    #[test]
+    fn big_uint_matches_u128() {
+        let values = [
+            0,
+            1,
+            u32::MAX as u128,
+            1 << 32,
+            u64::MAX as u128,
+            1 << 64,
+            (1 << 96) - 1,
+            123_456_789_012_345_678_901,
+        ];
+        for &a in &values {
+            assert_eq!(big(a).to_string(), a.to_string());
+            for &b in &values {
+                if let Some(sum) = a.checked_add(b) {
+                    assert_eq!(big(a).add(&big(b)), big(sum));
+                }
+                if let Some(product) = a.checked_mul(b) {
+                    assert_eq!(big(a).mul(&big(b)), big(product));
+                    assert_eq!(big(a).mul(&big(b)).to_string(), product.to_string());
+                }
+            }
+        }
+        // chunks of the decimal output need their leading zeros
+        assert_eq!(
+            big(1_000_000_000_000_000_007).to_string(),
+            "1000000000000000007"
+        );
+    }
+
+    // matrix powers should match stepping one day at a time, for any lifecycle
+    #[test]
+    fn matrix_matches_stepping() {
+        let input = [0, 1, 2, 2, 3];
+        for (reset, newborn) in [(6, 8), (2, 4), (0, 3), (3, 3), (1, 5)] {
+            let lifecycle = Lifecycle::new(reset, newborn);
+            let history = lifecycle.history(&Exact, &input, 60);
+            for (day, buckets) in history.iter().enumerate() {
+                let total = buckets
+                    .iter()
+                    .fold(BigUint::from_u64(0), |acc, n| acc.add(n));
+                assert_eq!(
+                    lifecycle.population(&Exact, &input, day as u64),
+                    total,
+                    "{:?} day {}",
+                    lifecycle,
+                    day
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn modulo_matches_exact() {
+        let input = read_file("data/example.txt");
+        let lifecycle = Lifecycle::new(6, 8);
+        for modulus in [7, 1_000_000_007, u32::MAX as u64 + 15] {
+            for days in [0, 1, 80, 256, 1000] {
+                assert_eq!(
+                    lifecycle.population(&Modulo(modulus), &input, days),
+                    reduce(&lifecycle.population(&Exact, &input, days), modulus)
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be past the newborn timer")]
+    fn reset_past_newborn() {
+        Lifecycle::new(9, 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "Timer 5 is past the newborn timer 4")]
+    fn timer_past_newborn() {
+        Lifecycle::new(2, 4).population(&Exact, &[5], 10);
+    }
+
+    #[test]
+    fn growth_rate_converges() {
+        let lifecycle = Lifecycle::new(6, 8);
+        let rate = lifecycle.growth_rate();
+        assert!((rate - 1.0910).abs() < 1e-4);
+        let history = lifecycle.history(&Float, &[3, 4, 3, 1, 2], 1000);
+        let totals: Vec<f64> = history.iter().map(|b| b.iter().sum()).collect();
+        let error = |day: usize| (totals[day] / totals[day - 1] - rate).abs();
+        assert!(error(1000) < 1e-5);
+        assert!(error(1000) < error(500) && error(500) < error(100));
+
+        // with no gap between resetting and being born, every fish doubles every day
+        assert!((Lifecycle::new(0, 0).growth_rate() - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn csv() {
+        let input = read_file("data/example.txt");
+        let history = Lifecycle::new(6, 8).history(&Exact, &input, 2);
+        let path = env::temp_dir().join("day_6_history.csv");
+        write_csv(&path, &history).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "day,total,growth,timer_0,timer_1,timer_2,timer_3,timer_4,timer_5,timer_6,timer_7,timer_8
+0,5,,0,1,1,2,1,0,0,0,0
+1,5,1,1,1,2,1,0,0,0,0,0
+2,6,1.2,1,2,1,0,0,0,1,0,1
+"
+        );
+    }
 }
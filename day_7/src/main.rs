@@ -1,58 +1,278 @@
 use std::{
+    env,
     fs::File,
     io::{prelude::*, BufReader},
     path::Path,
 };
 
-fn read_file(filename: impl AsRef<Path>) -> Vec<i32> {
+fn read_file(filename: impl AsRef<Path>) -> Vec<Vec<i64>> {
     let file = File::open(filename).expect("File not found");
-    let buf = BufReader::new(file);
-    buf.lines()
-        .next()
-        .unwrap()
-        .unwrap()
-        .split(',')
-        .map(|s| s.parse().unwrap())
-        .collect()
-}
-
-fn get_min_fuel<F>(input: &[i32], f: F) -> i32
-where
-    F: Fn(i32, i32) -> i32,
-{
-    let min = *input.iter().min().unwrap();
-    let max = *input.iter().max().unwrap();
-    let mut min_fuel: Option<i32> = None;
-
-    for position in min..=max {
-        let mut fuel = 0;
-        for crab in input {
-            fuel += f(position, *crab);
+    let mut buf = BufReader::new(file);
+    let mut contents = String::new();
+    buf.read_to_string(&mut contents)
+        .expect("Failed to read file");
+    parse(&contents)
+}
+
+// either the usual single line of positions, or a "dimensions N" header followed by one x,y[,z...]
+// tuple per line for crabs in more dimensions
+// without the header "3,4" is always two crabs, so a single crab in 2D can't be mistaken for them
+fn parse(contents: &str) -> Vec<Vec<i64>> {
+    let mut lines = contents.lines().map(str::trim).filter(|l| !l.is_empty());
+    let first = lines.next().expect("No crabs");
+    match first.strip_prefix("dimensions") {
+        Some(dims) => {
+            let dims: usize = dims.trim().parse().expect("Invalid number of dimensions");
+            assert!(dims > 0, "Crabs need at least one dimension");
+            let crabs: Vec<Vec<i64>> = lines
+                .map(|line| {
+                    let crab: Vec<i64> = line
+                        .split(',')
+                        .map(|s| s.trim().parse().expect("Invalid position"))
+                        .collect();
+                    assert_eq!(crab.len(), dims, "Crab {:?} has the wrong dimensions", line);
+                    crab
+                })
+                .collect();
+            assert!(!crabs.is_empty(), "No crabs");
+            crabs
+        }
+        None => {
+            assert!(
+                lines.next().is_none(),
+                "Crabs in more than one dimension need a \"dimensions N\" header"
+            );
+            first
+                .split(',')
+                .map(|s| vec![s.trim().parse().expect("Invalid position")])
+                .collect()
         }
-        min_fuel = match min_fuel {
-            Some(n) => Some(n.min(fuel)),
-            None => Some(fuel),
-        };
     }
+}
+
+// one coordinate of every crab
+fn axis(input: &[Vec<i64>], axis: usize) -> Vec<i64> {
+    input.iter().map(|crab| crab[axis]).collect()
+}
 
-    min_fuel.unwrap()
+// combines the best (position, fuel) of each axis into a point and the total fuel
+fn combine(per_axis: impl Iterator<Item = (i64, i64)>) -> (Vec<i64>, i64) {
+    per_axis.fold((Vec::new(), 0), |(mut point, total), (position, fuel)| {
+        point.push(position);
+        (point, total + fuel)
+    })
+}
+
+fn format_point(point: &[i64]) -> String {
+    point
+        .iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// sorted positions and prefix sums, so total fuel at a position doesn't need to look at every crab
+struct Crabs {
+    sorted: Vec<i64>,
+    prefix: Vec<i64>, // prefix[i] = sum of the first i sorted positions
+    sum_sq: i64,
+}
+
+impl Crabs {
+    fn new(input: &[i64]) -> Self {
+        let mut sorted = input.to_vec();
+        sorted.sort_unstable();
+        let mut prefix = vec![0];
+        for crab in &sorted {
+            prefix.push(prefix.last().unwrap() + crab);
+        }
+        let sum_sq = sorted.iter().map(|c| c * c).sum();
+        Crabs {
+            sorted,
+            prefix,
+            sum_sq,
+        }
+    }
+
+    fn len(&self) -> i64 {
+        self.sorted.len() as i64
+    }
+
+    fn sum(&self) -> i64 {
+        *self.prefix.last().unwrap()
+    }
+
+    // sum of |position - crab|, split into the crabs left and right of position
+    fn linear_fuel(&self, position: i64) -> i64 {
+        let left = self.sorted.partition_point(|&c| c < position);
+        let right = self.sorted.len() - left;
+        (position * left as i64 - self.prefix[left])
+            + (self.sum() - self.prefix[left] - position * right as i64)
+    }
+
+    // sum of d * (d + 1) / 2 = (sum of d^2 + sum of d) / 2
+    fn triangular_fuel(&self, position: i64) -> i64 {
+        let sum_sq_dist =
+            self.len() * position * position - 2 * position * self.sum() + self.sum_sq;
+        (sum_sq_dist + self.linear_fuel(position)) / 2
+    }
+
+    // the median minimizes the sum of distances
+    fn min_linear(&self) -> (i64, i64) {
+        let median = self.sorted[(self.sorted.len() - 1) / 2];
+        (median, self.linear_fuel(median))
+    }
+
+    // the optimum is always within 1/2 of the mean, so just check the closest integers
+    fn min_triangular(&self) -> (i64, i64) {
+        let mean = self.sum().div_euclid(self.len());
+        (mean - 1..=mean + 1)
+            .map(|p| (p, self.triangular_fuel(p)))
+            .min_by_key(|&(_, fuel)| fuel)
+            .unwrap()
+    }
+
+    // works for any cost f(position, crab) that's convex in the position, by binary searching for
+    // where it stops decreasing, so it's O(n log range) instead of O(log n) like the closed forms
+    // the total saturates instead of overflowing
+    fn min_convex(&self, f: impl Fn(i64, i64) -> i64) -> (i64, i64) {
+        let fuel = |position| {
+            self.sorted
+                .iter()
+                .map(|&crab| f(position, crab))
+                .fold(0, i64::saturating_add)
+        };
+        let mut lo = self.sorted[0];
+        let mut hi = *self.sorted.last().unwrap();
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if fuel(mid) <= fuel(mid + 1) {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        (lo, fuel(lo))
+    }
 }
 
 fn main() {
-    let input = read_file("data/input.txt");
+    let input = read_file(
+        env::args()
+            .nth(1)
+            .unwrap_or_else(|| "data/input.txt".to_string()),
+    );
+    let axes: Vec<Crabs> = (0..input[0].len())
+        .map(|a| Crabs::new(&axis(&input, a)))
+        .collect();
 
     // part 1
+    let (position, fuel) = combine(axes.iter().map(Crabs::min_linear));
     println!(
-        "Part 1: {} fuel",
-        get_min_fuel(&input, |a, b| (a - b).abs())
+        "Part 1: {} fuel at position {}",
+        fuel,
+        format_point(&position)
     );
 
     // part 2
+    let (position, fuel) = combine(axes.iter().map(Crabs::min_triangular));
     println!(
-        "Part 1: {} fuel",
-        get_min_fuel(&input, |a, b| {
-            let dist = (a - b).abs();
-            dist * (dist + 1) / 2
-        })
+        "Part 2: {} fuel at position {}",
+        fuel,
+        format_point(&position)
     );
+
+    // any other cost of the form distance^power can be passed after the input file
+    if let Some(power) = env::args().nth(2) {
+        let power: u32 = power.parse().expect("Invalid power");
+        assert!(power >= 1, "The cost has to be convex");
+        let (position, fuel) = combine(
+            axes.iter()
+                .map(|crabs| crabs.min_convex(|a, b| (a - b).abs().saturating_pow(power))),
+        );
+        println!(
+            "Distance^{}: {} fuel at position {}",
+            power,
+            fuel,
+            format_point(&position)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangular(a: i64, b: i64) -> i64 {
+        let dist = (a - b).abs();
+        dist * (dist + 1) / 2
+    }
+
+    // the closed forms should always agree with the binary search over the naive cost
+    fn check(input: &[Vec<i64>]) {
+        let axes: Vec<Crabs> = (0..input[0].len())
+            .map(|a| Crabs::new(&axis(input, a)))
+            .collect();
+        assert_eq!(
+            combine(axes.iter().map(Crabs::min_linear)).1,
+            combine(axes.iter().map(|c| c.min_convex(|a, b| (a - b).abs()))).1
+        );
+        assert_eq!(
+            combine(axes.iter().map(Crabs::min_triangular)).1,
+            combine(axes.iter().map(|c| c.min_convex(triangular))).1
+        );
+    }
+
+    #[test]
+    fn example() {
+        let input = read_file("data/example.txt");
+        let crabs = Crabs::new(&axis(&input, 0));
+        assert_eq!(crabs.min_linear(), (2, 37));
+        assert_eq!(crabs.min_triangular(), (5, 168));
+        check(&input);
+    }
+
+    #[test]
+    fn convex_costs() {
+        let crabs = Crabs::new(&axis(&read_file("data/example.txt"), 0));
+        // squared distance is minimized at the mean, which is 4.9 here
+        assert_eq!(crabs.min_convex(|a, b| (a - b) * (a - b)), (5, 291));
+        // a constant cost is flat, so the leftmost crab is as good as anywhere
+        assert_eq!(crabs.min_convex(|_, _| 1), (0, 10));
+        // asymmetric costs pull the position towards the cheap side
+        let (position, _) = crabs.min_convex(|a, b| if a > b { a - b } else { 10 * (b - a) });
+        assert_eq!(position, 16);
+    }
+
+    #[test]
+    fn example_3d() {
+        let input = read_file("data/example_3d.txt");
+        assert_eq!(input.len(), 5);
+        check(&input);
+    }
+
+    #[test]
+    fn negative_and_single() {
+        check(&parse("-5,-3,10,-100,7"));
+        check(&parse("42"));
+        check(&parse("dimensions 2\n-3,4"));
+    }
+
+    #[test]
+    fn header_decides_dimensions() {
+        assert_eq!(parse("3,4"), [[3], [4]]);
+        assert_eq!(parse("dimensions 2\n3,4\n"), [[3, 4]]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn tuples_without_header() {
+        parse("3,4\n5,6");
+    }
+
+    #[test]
+    #[should_panic]
+    fn wrong_dimensions() {
+        parse("dimensions 3\n1,2,3\n4,5");
+    }
 }
@@ -1,37 +1,41 @@
 use std::{
-    collections::{HashMap, HashSet},
-    fs::File,
+    collections::HashMap,
+    env,
+    fs::{self, File},
     io::{prelude::*, BufReader},
     path::Path,
     str::FromStr,
+    time::Instant,
 };
 
 struct ParseErr;
 
+// patterns are bitmasks of the wires that are on, with wire a as the lowest bit
 #[derive(Debug)]
 struct Entry {
-    signals: Vec<HashSet<char>>,
-    output: Vec<HashSet<char>>,
+    signals: Vec<u32>,
+    output: Vec<u32>,
 }
 
 impl FromStr for Entry {
     type Err = ParseErr;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut split = s.split(" | ");
+        // either side can have any number of patterns, including none
+        let mut split = s.split('|');
         Ok(Entry {
             signals: split
                 .next()
                 .ok_or(ParseErr)?
-                .split(' ')
-                .map(|s| s.chars().collect())
-                .collect(),
+                .split_whitespace()
+                .map(letters_to_mask)
+                .collect::<Result<_, _>>()?,
             output: split
                 .next()
                 .ok_or(ParseErr)?
-                .split(' ')
-                .map(|s| s.chars().collect())
-                .collect(),
+                .split_whitespace()
+                .map(letters_to_mask)
+                .collect::<Result<_, _>>()?,
         })
     }
 }
@@ -44,11 +48,436 @@ fn read_file(filename: impl AsRef<Path>) -> Vec<Entry> {
         .collect()
 }
 
+// one glyph per line, as its name followed by the segments that are lit, eg. "1 cf"
+// segments are letters starting from a, and wires use the same letters
+const SEVEN_SEGMENT: &str = "0 abcefg
+1 cf
+2 acdeg
+3 acdfg
+4 bcdf
+5 abdfg
+6 abdefg
+7 acf
+8 abcdefg
+9 abcdfg";
+
+fn letters_to_mask(s: &str) -> Result<u32, ParseErr> {
+    s.chars().try_fold(0, |mask, ch| match ch {
+        'a'..='z' => Ok(mask | 1 << (ch as u32 - 'a' as u32)),
+        _ => Err(ParseErr),
+    })
+}
+
+#[derive(Debug)]
+struct Display {
+    segments: usize,
+    glyphs: Vec<(String, u32)>,
+    lookup: HashMap<u32, usize>, // segment mask to index in glyphs
+}
+
+impl FromStr for Display {
+    type Err = ParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut glyphs = Vec::new();
+        for line in s.lines().filter(|l| !l.trim().is_empty()) {
+            let mut split = line.split_whitespace();
+            let name = split.next().ok_or(ParseErr)?.to_string();
+            glyphs.push((name, letters_to_mask(split.next().ok_or(ParseErr)?)?));
+        }
+        let all = glyphs.iter().fold(0, |acc, (_, mask)| acc | mask);
+        let lookup: HashMap<_, _> = glyphs
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, m))| (m, i))
+            .collect();
+        if lookup.len() != glyphs.len() {
+            return Err(ParseErr); // two glyphs with the same segments could never be told apart
+        }
+        Ok(Display {
+            segments: 32 - all.leading_zeros() as usize,
+            glyphs,
+            lookup,
+        })
+    }
+}
+
+// a 7 segment display has at most 5040 mappings, but a 16 segment one with few patterns can have
+// trillions of them, so displays with more segments than this only keep MAPPING_LIMIT of them
+const ALL_MAPPINGS_SEGMENTS: usize = 8;
+const MAPPING_LIMIT: usize = 1000;
+
+#[derive(Debug)]
+struct Decoded {
+    mappings: Vec<Vec<usize>>, // every wire index to segment index mapping that fits, up to the limit
+    count: u64,                // how many mappings fit, or a lower bound if not exhaustive
+    // false if the limit was hit and the search stopped once more mappings couldn't change the digits
+    exhaustive: bool,
+    digits: Vec<Vec<usize>>, // indexes of the glyphs each output pattern could be
+}
+
+// a pattern and the glyph masks it could be
+type Constraint = (u32, Vec<u32>);
+
+// what the search has found so far
+struct Found<'a> {
+    output: &'a [u32],
+    possible: Vec<Vec<bool>>, // possible[i][glyph] if output pattern i could be that glyph
+    missing: usize,           // glyphs that outputs have the right length for but haven't been seen
+    limit: Option<usize>,
+    mappings: Vec<Vec<usize>>,
+    count: u64,
+}
+
+impl Decoded {
+    // only if every output digit is known, even if the wires aren't
+    fn output<'a>(&self, display: &'a Display) -> Option<String> {
+        self.digits
+            .iter()
+            .map(|d| match d[..] {
+                [glyph] => Some(display.glyphs[glyph].0.as_str()),
+                _ => None,
+            })
+            .collect::<Option<Vec<&'a str>>>()
+            .map(|d| d.concat())
+    }
+}
+
+impl Display {
+    // lengths that only one glyph has, so a pattern of that length can only be that glyph
+    fn unique_lengths(&self) -> Vec<u32> {
+        let mut counts = HashMap::new();
+        for (_, mask) in &self.glyphs {
+            *counts.entry(mask.count_ones()).or_insert(0) += 1;
+        }
+        counts
+            .into_iter()
+            .filter(|&(_, count)| count == 1)
+            .map(|(len, _)| len)
+            .collect()
+    }
+
+    fn candidates(&self, pattern: u32) -> Vec<u32> {
+        self.glyphs
+            .iter()
+            .map(|&(_, mask)| mask)
+            .filter(|mask| mask.count_ones() == pattern.count_ones())
+            .collect()
+    }
+
+    // every observed pattern, signal or output, has to be some glyph
+    // with a limit, only that many mappings are kept, and the rest are only counted
+    fn solve(&self, signals: &[u32], output: &[u32], limit: Option<usize>) -> Decoded {
+        let patterns: Vec<u32> = signals.iter().chain(output).copied().collect();
+        let possible: Vec<Vec<bool>> = output
+            .iter()
+            .map(|_| vec![false; self.glyphs.len()])
+            .collect();
+        let mut found = Found {
+            output,
+            possible,
+            missing: output.iter().map(|&p| self.candidates(p).len()).sum(),
+            limit,
+            mappings: Vec::new(),
+            count: 0,
+        };
+        let exhaustive = match self.constraints(&patterns) {
+            Some((constraints, domains)) => !self.search(
+                &constraints,
+                &domains,
+                &mut vec![None; self.segments],
+                0,
+                &mut found,
+            ),
+            None => true,
+        };
+        let digits = found
+            .possible
+            .iter()
+            .map(|glyphs| (0..glyphs.len()).filter(|&g| glyphs[g]).collect())
+            .collect();
+        Decoded {
+            mappings: found.mappings,
+            count: found.count,
+            exhaustive,
+            digits,
+        }
+    }
+
+    // the candidate glyphs for each pattern, and the segments each wire could be
+    // None if some pattern can't be any glyph
+    fn constraints(&self, patterns: &[u32]) -> Option<(Vec<Constraint>, Vec<u32>)> {
+        let full = (1 << self.segments) - 1;
+        let mut constraints = Vec::new();
+        let mut domains = vec![full; self.segments];
+        for &pattern in patterns {
+            let candidates = self.candidates(pattern);
+            if candidates.is_empty() || pattern & !full != 0 {
+                return None;
+            }
+            // wires in the pattern have to be lit in some candidate, and the others unlit in some candidate
+            let lit = candidates.iter().fold(0, |acc, c| acc | c);
+            let unlit = candidates.iter().fold(0, |acc, c| acc | (!c & full));
+            for (wire, domain) in domains.iter_mut().enumerate() {
+                *domain &= if pattern & 1 << wire != 0 { lit } else { unlit };
+            }
+            constraints.push((pattern, candidates));
+        }
+        Some((constraints, domains))
+    }
+
+    // backtracking over wires, always picking the one with the fewest segments left
+    // returns true if it stopped early, because every output already has every glyph it could be
+    fn search(
+        &self,
+        constraints: &[Constraint],
+        domains: &[u32],
+        mapping: &mut Vec<Option<usize>>,
+        used: u32,
+        found: &mut Found,
+    ) -> bool {
+        let wire = match (0..mapping.len())
+            .filter(|&w| mapping[w].is_none())
+            .min_by_key(|&w| (domains[w] & !used).count_ones())
+        {
+            Some(wire) => wire,
+            None => {
+                let mapping: Vec<usize> = mapping.iter().map(|s| s.unwrap()).collect();
+                for (i, &pattern) in found.output.iter().enumerate() {
+                    if let Some(glyph) = self.decode(&mapping, pattern) {
+                        if !found.possible[i][glyph] {
+                            found.possible[i][glyph] = true;
+                            found.missing -= 1;
+                        }
+                    }
+                }
+                found.count += 1;
+                if found.limit.is_none_or(|limit| found.mappings.len() < limit) {
+                    found.mappings.push(mapping);
+                }
+                return found.missing == 0
+                    && found
+                        .limit
+                        .is_some_and(|limit| found.mappings.len() >= limit);
+            }
+        };
+        for segment in 0..self.segments {
+            if (domains[wire] & !used) & 1 << segment == 0 {
+                continue;
+            }
+            mapping[wire] = Some(segment);
+            // every pattern still needs a candidate that agrees with all the wires assigned so far
+            let consistent = constraints.iter().all(|(pattern, candidates)| {
+                candidates.iter().any(|c| {
+                    mapping.iter().enumerate().all(|(w, s)| match s {
+                        Some(s) => (pattern & 1 << w != 0) == (c & 1 << s != 0),
+                        None => true,
+                    })
+                })
+            });
+            if consistent && self.search(constraints, domains, mapping, used | 1 << segment, found)
+            {
+                mapping[wire] = None;
+                return true;
+            }
+            mapping[wire] = None;
+        }
+        false
+    }
+
+    fn decode(&self, mapping: &[usize], pattern: u32) -> Option<usize> {
+        let segments = mapping
+            .iter()
+            .enumerate()
+            .filter(|&(wire, _)| pattern & 1 << wire != 0)
+            .fold(0, |acc, (_, s)| acc | 1 << s);
+        self.lookup.get(&segments).copied()
+    }
+}
+
+// fast path for the standard display, which doesn't allocate at all
+// segments a-g are bits 0-6 like the wires, and STANDARD_LOOKUP maps segment masks to digits
+const STANDARD_DIGITS: [u8; 10] = [
+    0b1110111, 0b0100100, 0b1011101, 0b1101101, 0b0101110, 0b1101011, 0b1111011, 0b0100101,
+    0b1111111, 0b1101111,
+];
+
+const STANDARD_LOOKUP: [u8; 128] = {
+    let mut lookup = [u8::MAX; 128];
+    let mut digit = 0;
+    while digit < 10 {
+        lookup[STANDARD_DIGITS[digit] as usize] = digit as u8;
+        digit += 1;
+    }
+    lookup
+};
+
+/*
+standard 7 digit 0-9, letters appear this many times:
+    a: 8 *
+    b: 6
+    c: 8 *
+    d: 7 *
+    e: 4
+    f: 9
+    g: 7 *
+so only need to tell a/c and d/g apart
+c appears in 1 (len 2) but a doesn't
+d appears in 4 (len 4) but g doesn't
+*/
+// only works when all ten digits are in the signals, otherwise returns None
+fn decode_standard(signals: &[u32], output: &[u32]) -> Option<u32> {
+    if signals.len() != 10 || signals.iter().chain(output).any(|&p| p >= 1 << 7) {
+        return None;
+    }
+    let signals = signals.iter().map(|&p| p as u8);
+    let pattern_1 = signals.clone().find(|p| p.count_ones() == 2)?;
+    let pattern_4 = signals.clone().find(|p| p.count_ones() == 4)?;
+
+    let mut letter_map = [0_u8; 7]; // wire to segment bit
+    for (wire, segment) in letter_map.iter_mut().enumerate() {
+        let bit = 1 << wire;
+        *segment = 1
+            << match signals.clone().filter(|p| p & bit != 0).count() {
+                8 if pattern_1 & bit != 0 => 2, // c
+                8 => 0,                         // a
+                6 => 1,                         // b
+                7 if pattern_4 & bit != 0 => 3, // d
+                7 => 6,                         // g
+                4 => 4,                         // e
+                9 => 5,                         // f
+                _ => return None,
+            };
+    }
+
+    // the counts only hold up if the wires are a permutation and the signals
+    // really are the ten digits, otherwise leave it to the general solver
+    if letter_map.iter().fold(0, |acc, &segment| acc | segment) != 0x7F {
+        return None;
+    }
+    let digit = |pattern: u32| {
+        let segments = (0..7)
+            .filter(|wire| pattern & 1 << wire != 0)
+            .fold(0, |acc, wire| acc | letter_map[wire]);
+        match STANDARD_LOOKUP[segments as usize] {
+            u8::MAX => None,
+            digit => Some(digit as u32),
+        }
+    };
+    let seen = signals
+        .clone()
+        .try_fold(0_u16, |seen, p| Some(seen | 1 << digit(p as u32)?))?;
+    if seen != 0x3FF {
+        return None;
+    }
+
+    output
+        .iter()
+        .try_fold(0, |acc, &pattern| Some(acc * 10 + digit(pattern)?))
+}
+
+// simple xorshift, good enough for making up entries
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+// random standard entries with all ten signals and four output digits
+fn generate(count: usize, rng: &mut Rng) -> Vec<Entry> {
+    let scramble = |digit: u8, wires: &[usize; 7]| {
+        (0..7)
+            .filter(|segment| digit & 1 << segment != 0)
+            .fold(0, |acc, segment| acc | 1 << wires[segment])
+    };
+    (0..count)
+        .map(|_| {
+            let mut wires = [0, 1, 2, 3, 4, 5, 6]; // segment to wire
+            for i in (1..7).rev() {
+                wires.swap(i, rng.next(i + 1));
+            }
+            let mut signals: Vec<u32> = STANDARD_DIGITS
+                .iter()
+                .map(|&d| scramble(d, &wires))
+                .collect();
+            for i in (1..10).rev() {
+                signals.swap(i, rng.next(i + 1));
+            }
+            let output = (0..4)
+                .map(|_| scramble(STANDARD_DIGITS[rng.next(10)], &wires))
+                .collect();
+            Entry { signals, output }
+        })
+        .collect()
+}
+
+fn bench(count: usize) {
+    let entries = generate(count, &mut Rng(0x2545F4914F6CDD1D));
+
+    let start = Instant::now();
+    let sum: u64 = entries
+        .iter()
+        .map(|e| decode_standard(&e.signals, &e.output).unwrap() as u64)
+        .sum();
+    let elapsed = start.elapsed();
+    println!(
+        "Bitmask decoder: {} entries in {:?} ({:.0} entries/s, sum = {})",
+        count,
+        elapsed,
+        count as f64 / elapsed.as_secs_f64(),
+        sum
+    );
+
+    // the general solver is a lot slower, so only run it on part of the input
+    let display: Display = SEVEN_SEGMENT.parse().unwrap_or_else(|_| unreachable!());
+    let count = count.min(100_000);
+    let start = Instant::now();
+    let general_sum: u64 = entries[..count]
+        .iter()
+        .map(|e| {
+            let decoded = display.solve(&e.signals, &e.output, None);
+            decoded.output(&display).unwrap().parse::<u64>().unwrap()
+        })
+        .sum();
+    let elapsed = start.elapsed();
+    println!(
+        "Constraint solver: {} entries in {:?} ({:.0} entries/s, sum = {})",
+        count,
+        elapsed,
+        count as f64 / elapsed.as_secs_f64(),
+        general_sum
+    );
+}
+
 fn main() {
+    // "bench [count]" decodes lots of generated entries and prints the throughput
+    if env::args().nth(1).as_deref() == Some("bench") {
+        bench(
+            env::args()
+                .nth(2)
+                .map_or(1_000_000, |n| n.parse().expect("Invalid count")),
+        );
+        return;
+    }
+
     let input = read_file("data/input.txt");
 
+    // a different display can be passed as a file of glyphs in the same format as SEVEN_SEGMENT
+    let display: Display = match env::args().nth(1) {
+        Some(path) => fs::read_to_string(path).expect("File not found"),
+        None => SEVEN_SEGMENT.to_string(),
+    }
+    .parse()
+    .unwrap_or_else(|_| panic!("Invalid display definition"));
+    let standard = env::args().nth(1).is_none();
+
     // part 1
-    let valid_lengths = vec![2, 3, 4, 7];
+    let valid_lengths = display.unique_lengths();
     println!(
         "Part 1: {} times",
         input
@@ -56,94 +485,188 @@ fn main() {
             .map(|e| e
                 .output
                 .iter()
-                .filter(|s| valid_lengths.contains(&s.len()))
+                .filter(|s| valid_lengths.contains(&s.count_ones()))
                 .count())
             .sum::<usize>()
     );
 
     // part 2
-    /*
-    standard 7 digit 0-9, letters appear this many times:
-        a: 8 *
-        b: 6
-        c: 8 *
-        d: 7 *
-        e: 4
-        f: 9
-        g: 7 *
-    so only need to tell a/c and d/g apart
-    c appears in 1 (len 2) but a doesn't
-    d appears in 4 (len 4) but g doesn't
-    */
-    let mut real_digits: Vec<HashSet<char>> = Vec::new();
-    for s in [
-        "abcefg", "cf", "acdeg", "acdfg", "bcdf", "abdfg", "abdefg", "acf", "abcdefg", "abcdfg",
-    ]
-    .iter()
-    {
-        real_digits.push(s.chars().collect());
-    }
-    let real_digits = real_digits; // doesn't need to be mutable anymore
-
+    let limit = (display.segments > ALL_MAPPINGS_SEGMENTS).then_some(MAPPING_LIMIT);
     let mut output_sum = 0;
-    for entry in &input {
-        let mut letter_counts = HashMap::new();
-        let mut pattern_1 = None;
-        let mut pattern_4 = None;
-
-        for pattern in &entry.signals {
-            for ch in pattern {
-                *letter_counts.entry(*ch).or_insert(0) += 1;
+    let mut ambiguous = 0;
+    let mut unsolvable = 0;
+    for (i, entry) in input.iter().enumerate() {
+        if standard {
+            if let Some(n) = decode_standard(&entry.signals, &entry.output) {
+                output_sum += n as u64;
+                continue;
             }
-            match pattern.len() {
-                2 => pattern_1 = Some(pattern.clone()),
-                4 => pattern_4 = Some(pattern.clone()),
-                _ => (),
+        }
+        let decoded = display.solve(&entry.signals, &entry.output, limit);
+        if decoded.count == 0 {
+            println!("Entry {} is unsolvable", i);
+            unsolvable += 1;
+            continue;
+        }
+        match decoded.output(&display) {
+            // glyph names don't have to be digits, and then there's nothing to add up
+            Some(output) => match output.parse::<u64>() {
+                Ok(n) => output_sum += n,
+                Err(_) => println!("Entry {} reads {}", i, output),
+            },
+            None => {
+                let digits: Vec<String> = decoded
+                    .digits
+                    .iter()
+                    .map(|d| {
+                        let names: Vec<&str> =
+                            d.iter().map(|&g| display.glyphs[g].0.as_str()).collect();
+                        names.join("/")
+                    })
+                    .collect();
+                println!(
+                    "Entry {} is ambiguous, {}{} wire mappings fit (eg. {:?}), output could be {}",
+                    i,
+                    if decoded.exhaustive { "" } else { "at least " },
+                    decoded.count,
+                    decoded.mappings[0],
+                    digits.join(" ")
+                );
+                ambiguous += 1;
             }
         }
+    }
+    println!("Part 2: sum = {}", output_sum);
+    if ambiguous > 0 || unsolvable > 0 {
+        println!(
+            "{} ambiguous entries, {} unsolvable entries",
+            ambiguous, unsolvable
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn entry(s: &str) -> Entry {
+        s.parse().ok().unwrap()
+    }
 
-        let mut letter_map = HashMap::new();
-        let pattern_1 = pattern_1.unwrap();
-        let pattern_4 = pattern_4.unwrap();
-
-        for (ch, count) in &letter_counts {
-            letter_map.insert(
-                *ch,
-                match count {
-                    8 => match pattern_1.contains(ch) {
-                        // a or c
-                        true => 'c',
-                        false => 'a',
-                    },
-                    6 => 'b',
-                    7 => match pattern_4.contains(ch) {
-                        // d or g
-                        true => 'd',
-                        false => 'g',
-                    },
-                    4 => 'e',
-                    9 => 'f',
-                    _ => unreachable!(),
-                },
-            );
+    #[test]
+    fn example() {
+        let display: Display = SEVEN_SEGMENT.parse().ok().unwrap();
+        let mut sum = 0;
+        for entry in read_file("data/example.txt") {
+            let decoded = display.solve(&entry.signals, &entry.output, None);
+            assert_eq!((decoded.count, decoded.exhaustive), (1, true));
+            let output = decoded.output(&display).unwrap();
+            let fast = decode_standard(&entry.signals, &entry.output).unwrap();
+            assert_eq!(output.parse::<u32>().unwrap(), fast);
+            sum += fast;
         }
+        assert_eq!(sum, 61229);
+    }
 
-        output_sum += entry
-            .output
-            .iter()
-            .map(|p| {
-                real_digits
-                    .iter()
-                    .position(|rp| {
-                        rp == &p
-                            .iter()
-                            .map(|c| *letter_map.get(c).unwrap())
-                            .collect::<HashSet<char>>()
-                    })
-                    .unwrap()
-            })
-            .reduce(|acc, n| acc * 10 + n)
+    #[test]
+    fn letter_glyphs() {
+        // the standard digits, renamed to letters that look a bit like them
+        let display: Display = "o abcefg\ni cf\nz acdeg\ne acdfg\nh bcdf\ns abdfg\nb abdefg\n\
+                                l acf\nx abcdefg\ng abcdfg"
+            .parse()
+            .ok()
             .unwrap();
+        let entry = entry(
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+        );
+        let decoded = display.solve(&entry.signals, &entry.output, None);
+        assert_eq!(decoded.output(&display).as_deref(), Some("sese"));
+    }
+
+    #[test]
+    fn ambiguous_without_signals() {
+        let display: Display = SEVEN_SEGMENT.parse().ok().unwrap();
+        let entry = entry("| abcde");
+        let decoded = display.solve(&entry.signals, &entry.output, None);
+        assert_eq!(decoded.output(&display), None);
+        assert_eq!(decoded.digits, [[2, 3, 5]]);
+
+        // without a limit every mapping is kept, and they're all different
+        assert!(decoded.exhaustive);
+        assert_eq!(decoded.mappings.len() as u64, decoded.count);
+        let unique: HashSet<_> = decoded.mappings.iter().collect();
+        assert_eq!(unique.len(), decoded.mappings.len());
+        for mapping in &decoded.mappings {
+            assert!([2, 3, 5].contains(&display.decode(mapping, entry.output[0]).unwrap()));
+        }
+        // with a 7 segment display there are at most 7! of them
+        let decoded = display.solve(&[], &[], None);
+        assert_eq!(decoded.count, 5040);
+        assert_eq!(decoded.mappings.len(), 5040);
+    }
+
+    #[test]
+    fn huge_mapping_count() {
+        // any of the 16! wire mappings fits, but the search stops after a few
+        let display: Display = "full abcdefghijklmnop\nbar abcd\nslash aeim"
+            .parse()
+            .ok()
+            .unwrap();
+        let full = entry("| abcdefghijklmnop");
+        let decoded = display.solve(&full.signals, &full.output, Some(8));
+        assert_eq!(decoded.output(&display).as_deref(), Some("full"));
+        assert_eq!(decoded.mappings.len(), 8);
+        assert_eq!(decoded.count, 8);
+        assert!(!decoded.exhaustive);
+
+        let short = entry("| ab");
+        let decoded = display.solve(&short.signals, &short.output, Some(8));
+        assert_eq!(decoded.count, 0);
+    }
+
+    #[test]
+    fn corrupted_signals() {
+        // trading a wire between two signals leaves every wire count alone, so
+        // the fast path has to check it really got a permutation and ten digits
+        let display: Display = SEVEN_SEGMENT.parse().ok().unwrap();
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut fell_through = 0;
+        for good in read_file("data/example.txt")
+            .into_iter()
+            .chain(generate(10, &mut rng))
+        {
+            for (i, j) in (0..10).flat_map(|i| (i + 1..10).map(move |j| (i, j))) {
+                for (x, y) in (0..7).flat_map(|x| (0..7).map(move |y| (x, y))) {
+                    let flip = 1 << x | 1 << y;
+                    let (a, b) = (good.signals[i], good.signals[j]);
+                    if a & flip != 1 << x || b & flip != 1 << y {
+                        continue;
+                    }
+                    let mut signals = good.signals.clone();
+                    signals[i] ^= flip;
+                    signals[j] ^= flip;
+                    let decoded = display.solve(&signals, &good.output, None);
+                    let distinct = signals.iter().collect::<HashSet<_>>().len() == 10;
+                    // once in a while it's still ten digits, just wired differently
+                    let expected = match decoded.output(&display) {
+                        Some(output) if distinct && decoded.count == 1 => output.parse().ok(),
+                        _ => None,
+                    };
+                    let fast = decode_standard(&signals, &good.output);
+                    assert_eq!(fast, expected);
+                    fell_through += fast.is_none() as usize;
+                }
+            }
+        }
+        assert!(fell_through > 0);
+
+        // the first example with the 4 and the 7 trading c for d, which nothing fits
+        let bad = entry(
+            "be cfbegad cbdgef fgaecd dgeb fdcge agebfd fecdb fabcd ecb | fdgacbe cefdb cefbgd gcbe",
+        );
+        assert_eq!(decode_standard(&bad.signals, &bad.output), None);
+        let decoded = display.solve(&bad.signals, &bad.output, None);
+        assert_eq!((decoded.count, decoded.exhaustive), (0, true));
     }
-    println!("Part 2: sum = {}", output_sum);
 }
@@ -1,8 +1,11 @@
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    env,
     fs::File,
     io::{prelude::*, BufReader},
     num::TryFromIntError,
     path::Path,
+    str::FromStr,
 };
 
 #[derive(Debug)]
@@ -17,7 +20,6 @@ impl From<TryFromIntError> for ParseErr {
 #[derive(Debug, Clone, Copy)]
 struct Point {
     height: u8,
-    visited: bool,
 }
 
 impl TryFrom<char> for Point {
@@ -26,7 +28,6 @@ impl TryFrom<char> for Point {
     fn try_from(value: char) -> Result<Self, Self::Error> {
         Ok(Point {
             height: value.to_digit(10).ok_or(ParseErr)?.try_into()?,
-            visited: false,
         })
     }
 }
@@ -39,54 +40,339 @@ fn read_file(filename: impl AsRef<Path>) -> Vec<Vec<Point>> {
         .collect()
 }
 
-fn check_low(input: &[Vec<Point>], row_i: usize, col_i: usize) -> bool {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Neighbourhood {
+    Four,
+    Eight,
+    Hex, // odd rows are shifted right by half a cell
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Topology {
+    Bounded,
+    Torus, // wraps around both edges, hex maps need an even number of rows for this to line up
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Adjacency {
+    neighbourhood: Neighbourhood,
+    topology: Topology,
+}
+
+impl FromStr for Adjacency {
+    type Err = ParseErr;
+
+    // eg. "4", "8 torus", "hex bounded"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.split_whitespace();
+        let neighbourhood = match split.next() {
+            Some("4") | None => Neighbourhood::Four,
+            Some("8") => Neighbourhood::Eight,
+            Some("hex") => Neighbourhood::Hex,
+            _ => return Err(ParseErr),
+        };
+        let topology = match split.next() {
+            Some("bounded") | None => Topology::Bounded,
+            Some("torus") => Topology::Torus,
+            _ => return Err(ParseErr),
+        };
+        Ok(Adjacency {
+            neighbourhood,
+            topology,
+        })
+    }
+}
+
+impl Adjacency {
+    fn offsets(&self, row_i: usize) -> &'static [(isize, isize)] {
+        match self.neighbourhood {
+            Neighbourhood::Four => &[(-1, 0), (0, -1), (1, 0), (0, 1)],
+            Neighbourhood::Eight => &[
+                (-1, -1),
+                (-1, 0),
+                (-1, 1),
+                (0, -1),
+                (0, 1),
+                (1, -1),
+                (1, 0),
+                (1, 1),
+            ],
+            Neighbourhood::Hex if row_i.is_multiple_of(2) => {
+                &[(-1, -1), (-1, 0), (0, -1), (0, 1), (1, -1), (1, 0)]
+            }
+            Neighbourhood::Hex => &[(-1, 0), (-1, 1), (0, -1), (0, 1), (1, 0), (1, 1)],
+        }
+    }
+
+    // how many neighbours a cell has when it isn't on an edge
+    fn max_adjacent(&self) -> usize {
+        self.offsets(0).len()
+    }
+}
+
+fn check_low(input: &[Vec<Point>], row_i: usize, col_i: usize, adj: Adjacency) -> bool {
     let height = input[row_i][col_i].height;
-    (row_i == 0 || input[row_i - 1][col_i].height > height)
-        && (row_i == input.len() - 1 || input[row_i + 1][col_i].height > height)
-        && (col_i == 0 || input[row_i][col_i - 1].height > height)
-        && (col_i == input[0].len() - 1 || input[row_i][col_i + 1].height > height)
+    get_adjacent(input, row_i, col_i, adj)
+        .iter()
+        .all(|&(r, c)| input[r][c].height > height)
 }
 
-fn get_adjacent(input: &[Vec<Point>], row_i: usize, col_i: usize) -> Vec<(usize, usize)> {
+fn get_adjacent(
+    input: &[Vec<Point>],
+    row_i: usize,
+    col_i: usize,
+    adj: Adjacency,
+) -> Vec<(usize, usize)> {
+    let (rows, cols) = (input.len() as isize, input[0].len() as isize);
     let mut output = Vec::new();
-    if row_i > 0 {
-        output.push((row_i - 1, col_i));
+    for &(dr, dc) in adj.offsets(row_i) {
+        let (mut r, mut c) = (row_i as isize + dr, col_i as isize + dc);
+        match adj.topology {
+            Topology::Bounded if r < 0 || r >= rows || c < 0 || c >= cols => continue,
+            Topology::Bounded => (),
+            Topology::Torus => {
+                r = r.rem_euclid(rows);
+                c = c.rem_euclid(cols);
+            }
+        }
+        // tiny maps can wrap around onto the same cell
+        let cell = (r as usize, c as usize);
+        if cell != (row_i, col_i) && !output.contains(&cell) {
+            output.push(cell);
+        }
     }
-    if col_i > 0 {
-        output.push((row_i, col_i - 1));
+    output
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BasinMode {
+    Walls,    // basins are the areas between 9s, like the puzzle says
+    Drainage, // every cell flows to its lowest neighbour, and basins are where the water ends up
+}
+
+#[derive(Debug)]
+struct Basin {
+    size: usize,
+    low_point: (usize, usize),
+    boundary: Vec<(usize, usize)>, // cells next to another basin, a 9 or the edge of the map
+    bounding_box: ((usize, usize), (usize, usize)), // top left and bottom right, inclusive
+}
+
+#[derive(Debug)]
+struct Basins {
+    labels: Vec<Vec<Option<usize>>>, // index into basins for each cell, None if it's in no basin
+    basins: Vec<Basin>,
+    split: Vec<(usize, usize)>, // cells that drain into more than one basin
+}
+
+// flood fills each basin with a queue, so big maps can't overflow the stack
+fn label_walls(input: &[Vec<Point>], adj: Adjacency) -> Vec<Vec<Option<usize>>> {
+    let mut labels = vec![vec![None; input[0].len()]; input.len()];
+    let mut next_id = 0;
+    let mut queue = VecDeque::new();
+    for row_i in 0..input.len() {
+        for col_i in 0..input[row_i].len() {
+            if input[row_i][col_i].height == 9 || labels[row_i][col_i].is_some() {
+                continue;
+            }
+            labels[row_i][col_i] = Some(next_id);
+            queue.push_back((row_i, col_i));
+            while let Some((r, c)) = queue.pop_front() {
+                for (adj_r, adj_c) in get_adjacent(input, r, c, adj) {
+                    if input[adj_r][adj_c].height != 9 && labels[adj_r][adj_c].is_none() {
+                        labels[adj_r][adj_c] = Some(next_id);
+                        queue.push_back((adj_r, adj_c));
+                    }
+                }
+            }
+            next_id += 1;
+        }
     }
-    if row_i < input.len() - 1 {
-        output.push((row_i + 1, col_i));
+    labels
+}
+
+// connected areas of the same height that have no lower neighbours, so plateaus count as one
+fn find_minima(input: &[Vec<Point>], adj: Adjacency) -> Vec<Cells> {
+    let mut seen = vec![vec![false; input[0].len()]; input.len()];
+    let mut minima = Vec::new();
+    for row_i in 0..input.len() {
+        for col_i in 0..input[row_i].len() {
+            if seen[row_i][col_i] {
+                continue;
+            }
+            let (plateau, exits) = get_plateau(input, row_i, col_i, adj);
+            for &(r, c) in &plateau {
+                seen[r][c] = true;
+            }
+            if exits.is_empty() {
+                minima.push(plateau);
+            }
+        }
     }
-    if col_i < input[0].len() - 1 {
-        output.push((row_i, col_i + 1));
+    minima
+}
+
+type Cells = Vec<(usize, usize)>;
+
+// all cells connected to this one with the same height, and the cells of the plateau that have a
+// lower neighbour
+fn get_plateau(input: &[Vec<Point>], row_i: usize, col_i: usize, adj: Adjacency) -> (Cells, Cells) {
+    let height = input[row_i][col_i].height;
+    let mut plateau = vec![(row_i, col_i)];
+    let mut seen = HashSet::from([(row_i, col_i)]);
+    let mut exits = Vec::new();
+    let mut queue = VecDeque::from([(row_i, col_i)]);
+    while let Some((r, c)) = queue.pop_front() {
+        let adjacent = get_adjacent(input, r, c, adj);
+        if adjacent
+            .iter()
+            .any(|&(ar, ac)| input[ar][ac].height < height)
+        {
+            exits.push((r, c));
+        }
+        for adj in adjacent {
+            if input[adj.0][adj.1].height == height && seen.insert(adj) {
+                plateau.push(adj);
+                queue.push_back(adj);
+            }
+        }
     }
-    output
+    (plateau, exits)
 }
 
-fn get_basin_size(input: &mut [Vec<Point>], row_i: usize, col_i: usize) -> u32 {
-    if input[row_i][col_i].height == 9 {
-        return 0;
+// cells are handled from lowest to highest, so everything a cell can flow into is already done
+// returns the sorted basin ids each cell drains into
+fn drain(input: &[Vec<Point>], adj: Adjacency) -> Vec<Vec<Vec<usize>>> {
+    let mut drains_to: Vec<Vec<Vec<usize>>> = vec![vec![Vec::new(); input[0].len()]; input.len()];
+    let minima = find_minima(input, adj);
+    for (id, minimum) in minima.iter().enumerate() {
+        for &(r, c) in minimum {
+            drains_to[r][c] = vec![id];
+        }
     }
-    let mut basin_size = 0;
-    for (adj_row_i, adj_col_i) in &get_adjacent(input, row_i, col_i) {
-        let point = &mut input[*adj_row_i][*adj_col_i];
-        if !point.visited && point.height < 9 {
-            point.visited = true;
-            basin_size += get_basin_size(input, *adj_row_i, *adj_col_i) + 1;
+
+    let mut cells: Vec<(usize, usize)> = (0..input.len())
+        .flat_map(|r| (0..input[r].len()).map(move |c| (r, c)))
+        .collect();
+    cells.sort_by_key(|&(r, c)| input[r][c].height);
+    for (r, c) in cells {
+        if !drains_to[r][c].is_empty() {
+            continue;
+        }
+        // on a plateau, water flows across to the cells that can flow downhill
+        let (plateau, exits) = get_plateau(input, r, c, adj);
+        let mut exit_drains = HashMap::new();
+        for &(exit_r, exit_c) in &exits {
+            let adjacent = get_adjacent(input, exit_r, exit_c, adj);
+            let lowest = adjacent
+                .iter()
+                .map(|&(ar, ac)| input[ar][ac].height)
+                .min()
+                .unwrap();
+            let mut targets = Vec::new();
+            for (adj_r, adj_c) in adjacent {
+                if input[adj_r][adj_c].height == lowest {
+                    targets.extend(&drains_to[adj_r][adj_c]);
+                }
+            }
+            targets.sort_unstable();
+            targets.dedup();
+            exit_drains.insert((exit_r, exit_c), targets);
+        }
+        let mut all_targets: Vec<usize> = exit_drains.values().flatten().copied().collect();
+        all_targets.sort_unstable();
+        all_targets.dedup();
+        for cell in plateau {
+            drains_to[cell.0][cell.1] = match exit_drains.get(&cell) {
+                Some(targets) => targets.clone(),
+                None => all_targets.clone(),
+            };
         }
     }
-    basin_size
+    drains_to
+}
+
+fn label_basins(input: &[Vec<Point>], mode: BasinMode, adj: Adjacency) -> Basins {
+    let (labels, split) = match mode {
+        BasinMode::Walls => (label_walls(input, adj), Vec::new()),
+        BasinMode::Drainage => {
+            let drains_to = drain(input, adj);
+            let mut split = Vec::new();
+            let mut labels = vec![vec![None; input[0].len()]; input.len()];
+            for (row_i, row) in drains_to.iter().enumerate() {
+                for (col_i, targets) in row.iter().enumerate() {
+                    match targets[..] {
+                        [id] => labels[row_i][col_i] = Some(id),
+                        _ => split.push((row_i, col_i)),
+                    }
+                }
+            }
+            (labels, split)
+        }
+    };
+
+    // drainage ids follow the order minima were found in, not row-major order, so each basin is
+    // only created once its own first cell turns up
+    let mut basins: Vec<Option<Basin>> = Vec::new();
+    for (row_i, row) in labels.iter().enumerate() {
+        for (col_i, label) in row.iter().enumerate() {
+            let id = match label {
+                Some(id) => *id,
+                None => continue,
+            };
+            if basins.len() <= id {
+                basins.resize_with(id + 1, || None);
+            }
+            let basin = basins[id].get_or_insert_with(|| Basin {
+                size: 0,
+                low_point: (row_i, col_i),
+                boundary: Vec::new(),
+                bounding_box: ((row_i, col_i), (row_i, col_i)),
+            });
+            basin.size += 1;
+            let (low_r, low_c) = basin.low_point;
+            if input[row_i][col_i].height < input[low_r][low_c].height {
+                basin.low_point = (row_i, col_i);
+            }
+            let ((top, left), (bottom, right)) = basin.bounding_box;
+            basin.bounding_box = (
+                (top.min(row_i), left.min(col_i)),
+                (bottom.max(row_i), right.max(col_i)),
+            );
+            let adjacent = get_adjacent(input, row_i, col_i, adj);
+            if adjacent.len() < adj.max_adjacent()
+                || adjacent.iter().any(|&(ar, ac)| labels[ar][ac] != Some(id))
+            {
+                basin.boundary.push((row_i, col_i));
+            }
+        }
+    }
+    Basins {
+        labels,
+        basins: basins
+            .into_iter()
+            .map(|b| b.expect("Every basin has a cell"))
+            .collect(),
+        split,
+    }
 }
 
 fn main() {
     let input = read_file("data/input.txt");
 
+    // the neighbourhood and topology can be passed as arguments, eg. "8 torus"
+    let adj: Adjacency = env::args()
+        .skip(1)
+        .collect::<Vec<_>>()
+        .join(" ")
+        .parse()
+        .expect("Invalid neighbourhood or topology");
+
     // part 1
     let mut risk_sum = 0;
     for (row_i, row) in input.iter().enumerate() {
         for (col_i, point) in row.iter().enumerate() {
-            if check_low(&input, row_i, col_i) {
+            if check_low(&input, row_i, col_i, adj) {
                 risk_sum += point.height as u32 + 1;
             }
         }
@@ -94,19 +380,107 @@ fn main() {
     println!("Part 1: risk_sum = {}", risk_sum);
 
     // part 2
-    let mut input = input;
-    let mut basins = Vec::new();
-    for row_i in 0..input.len() {
-        for col_i in 0..input[row_i].len() {
-            let point = input[row_i][col_i];
-            if point.height != 9 && !point.visited {
-                basins.push(get_basin_size(&mut input, row_i, col_i));
-            }
-        }
-    }
-    basins.sort_unstable();
+    let Basins { labels, basins, .. } = label_basins(&input, BasinMode::Walls, adj);
+    let mut sizes: Vec<usize> = basins.iter().map(|b| b.size).collect();
+    sizes.sort_unstable();
     println!(
         "Part 2: product = {}",
-        basins.iter().rev().take(3).product::<u32>()
+        sizes.iter().rev().take(3).product::<usize>()
+    );
+
+    let largest = basins.iter().max_by_key(|b| b.size).unwrap();
+    println!(
+        "{} of {} cells are in {} basins",
+        labels.iter().flatten().filter(|l| l.is_some()).count(),
+        input.len() * input[0].len(),
+        basins.len()
+    );
+    println!(
+        "Largest basin has size {}, low point {:?}, {} boundary cells, bounding box {:?}",
+        largest.size,
+        largest.low_point,
+        largest.boundary.len(),
+        largest.bounding_box
     );
+
+    let minima = find_minima(&input, adj);
+    println!(
+        "{} low areas counting plateaus, {} of them more than one cell",
+        minima.len(),
+        minima.iter().filter(|m| m.len() > 1).count()
+    );
+    let drainage = label_basins(&input, BasinMode::Drainage, adj);
+    let mut sizes: Vec<usize> = drainage.basins.iter().map(|b| b.size).collect();
+    sizes.sort_unstable();
+    println!(
+        "Drainage: {} basins, largest three {:?}, {} cells drain into more than one basin",
+        drainage.basins.len(),
+        sizes.iter().rev().take(3).collect::<Vec<_>>(),
+        drainage.split.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Vec<Vec<Point>> {
+        s.split('/')
+            .map(|row| row.chars().map(|c| c.try_into().unwrap()).collect())
+            .collect()
+    }
+
+    // every basin's stats should only come from the cells labelled with it
+    fn check(input: &[Vec<Point>], basins: &Basins) {
+        for (id, basin) in basins.basins.iter().enumerate() {
+            let cells: Vec<(usize, usize)> = (0..input.len())
+                .flat_map(|r| (0..input[r].len()).map(move |c| (r, c)))
+                .filter(|&(r, c)| basins.labels[r][c] == Some(id))
+                .collect();
+            assert_eq!(basin.size, cells.len());
+            let top = cells.iter().map(|c| c.0).min().unwrap();
+            let left = cells.iter().map(|c| c.1).min().unwrap();
+            let bottom = cells.iter().map(|c| c.0).max().unwrap();
+            let right = cells.iter().map(|c| c.1).max().unwrap();
+            assert_eq!(basin.bounding_box, ((top, left), (bottom, right)));
+            assert!(cells.contains(&basin.low_point));
+            let (low_r, low_c) = basin.low_point;
+            assert!(cells
+                .iter()
+                .all(|&(r, c)| input[r][c].height >= input[low_r][low_c].height));
+        }
+    }
+
+    #[test]
+    fn drainage_ids_out_of_order() {
+        let input = parse("9594/3919");
+        let adj: Adjacency = "4".parse().unwrap();
+        let basins = label_basins(&input, BasinMode::Drainage, adj);
+        check(&input, &basins);
+        let single = basins
+            .basins
+            .iter()
+            .find(|b| b.low_point == (0, 3))
+            .unwrap();
+        assert_eq!(single.bounding_box, ((0, 3), (0, 3)));
+    }
+
+    #[test]
+    fn example() {
+        let input = read_file("data/example.txt");
+        for adj in ["4", "8", "hex", "4 torus"] {
+            let adj: Adjacency = adj.parse().unwrap();
+            for mode in [BasinMode::Walls, BasinMode::Drainage] {
+                check(&input, &label_basins(&input, mode, adj));
+            }
+        }
+        let adj: Adjacency = "4".parse().unwrap();
+        let mut sizes: Vec<usize> = label_basins(&input, BasinMode::Walls, adj)
+            .basins
+            .iter()
+            .map(|b| b.size)
+            .collect();
+        sizes.sort_unstable();
+        assert_eq!(sizes, [3, 9, 9, 14]);
+    }
 }